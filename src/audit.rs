@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDate, Utc, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use crate::logger;
+use crate::models::spend::{SpendPeriod, SpendReport};
+use crate::request_context::RequestContext;
+use crate::retry::Attempt;
+
+const FILE_PREFIX: &str = "audit-";
+const FILE_SUFFIX: &str = ".jsonl";
+const FILE_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// One upstream call, stored as a line of JSON
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    time: DateTime<Utc>,
+    request_id: String,
+    client: String,
+    route: String,
+    resource: String,
+    /// Estimated from the cost table, YouTube doesn't report the actual cost
+    cost: usize,
+    key: String,
+    status: Option<u16>,
+    outcome: String,
+    latency_ms: u64,
+}
+
+impl AuditRecord {
+    pub fn new(ctx: &RequestContext, resource: &str, cost: usize, attempt: &Attempt) -> Self {
+        AuditRecord {
+            time: Utc::now(),
+            request_id: ctx.get_request_id().to_string(),
+            client: ctx.get_client_name().to_string(),
+            route: ctx.get_route().to_string(),
+            resource: resource.to_string(),
+            cost,
+            key: attempt.get_label().to_string(),
+            status: attempt.get_status(),
+            outcome: attempt.get_outcome().to_string(),
+            latency_ms: attempt.get_elapsed().as_millis() as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    Day,
+    Month,
+}
+
+impl Period {
+    pub fn parse(value: &str) -> Option<Period> {
+        match value {
+            "day" => Some(Period::Day),
+            "month" => Some(Period::Month),
+            _ => None
+        }
+    }
+
+    fn label(self, time: &DateTime<Utc>) -> String {
+        match self {
+            Period::Day => time.format("%Y-%m-%d").to_string(),
+            Period::Month => time.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+/// Appends upstream calls to a file per UTC day in `dir`, files older than the retention period are deleted
+pub struct AuditLog {
+    dir: PathBuf,
+    retention_days: u32,
+    current: Mutex<Option<(NaiveDate, File)>>,
+}
+
+impl AuditLog {
+    pub fn new(dir: PathBuf, retention_days: u32) -> Result<AuditLog> {
+        fs::create_dir_all(&dir)?;
+        Ok(AuditLog {
+            dir,
+            retention_days,
+            current: Mutex::new(None),
+        })
+    }
+}
+
+impl AuditLog {
+    /// Writes to disk, so call it from a blocking task rather than an async worker
+    pub fn record(&self, record: &AuditRecord) -> Result<()> {
+        let date = record.time.date_naive();
+        let mut current = self.current.lock().unwrap();
+        if current.as_ref().is_none_or(|(current_date, _)| *current_date != date) {
+            let file = OpenOptions::new().create(true).append(true).open(self.file_path(date))?;
+            *current = Some((date, file));
+            self.remove_expired(date);
+        }
+        let (_, file) = current.as_mut().unwrap();
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    /// Records with a time between `from` and `to` inclusive, oldest first
+    pub fn read(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<AuditRecord>> {
+        let (first, last) = (from.date_naive(), to.date_naive());
+        let mut files: Vec<(NaiveDate, PathBuf)> = self.files().into_iter()
+            .filter(|(date, _)| *date >= first && *date <= last)
+            .collect();
+        files.sort();
+
+        let mut records = vec![];
+        for (_, path) in files {
+            for line in BufReader::new(File::open(&path)?).lines() {
+                // a crash mid write can leave a partial line, it's skipped rather than failing the whole report
+                match serde_json::from_str::<AuditRecord>(&line?) {
+                    Ok(record) if record.time >= from && record.time <= to => records.push(record),
+                    Ok(_) => {}
+                    Err(error) => logger::warn(None, "Skipping unreadable audit record", &[
+                        ("path", path.display().to_string()),
+                        ("error", error.to_string())])
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    pub fn spend(&self, from: DateTime<Utc>, to: DateTime<Utc>, period: Period) -> Result<SpendReport> {
+        let mut periods: BTreeMap<String, SpendPeriod> = BTreeMap::new();
+        for record in self.read(from, to)? {
+            let label = period.label(&record.time);
+            periods.entry(label.clone())
+                .or_insert_with(|| SpendPeriod::new(label))
+                .add(&record.client, &record.route, &record.resource, &record.key, record.cost);
+        }
+        Ok(SpendReport::new(
+            from.to_rfc3339_opts(SecondsFormat::Secs, true),
+            to.to_rfc3339_opts(SecondsFormat::Secs, true),
+            periods.into_values().collect(),
+        ))
+    }
+
+    fn file_path(&self, date: NaiveDate) -> PathBuf {
+        self.dir.join(format!("{}{}{}", FILE_PREFIX, date.format(FILE_DATE_FORMAT), FILE_SUFFIX))
+    }
+
+    fn files(&self) -> Vec<(NaiveDate, PathBuf)> {
+        match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| file_date(&entry.path()).map(|date| (date, entry.path())))
+                .collect(),
+            Err(error) => {
+                logger::error(None, "Unable to list audit files", &[("error", error.to_string())]);
+                vec![]
+            }
+        }
+    }
+
+    fn remove_expired(&self, today: NaiveDate) {
+        let cutoff = today - Duration::days(self.retention_days as i64);
+        for (date, path) in self.files() {
+            if date < cutoff {
+                if let Err(error) = fs::remove_file(&path) {
+                    logger::warn(None, "Unable to remove expired audit file", &[
+                        ("path", path.display().to_string()),
+                        ("error", error.to_string())]);
+                }
+            }
+        }
+    }
+}
+
+fn file_date(path: &Path) -> Option<NaiveDate> {
+    let name = path.file_name()?.to_str()?;
+    let date = name.strip_prefix(FILE_PREFIX)?.strip_suffix(FILE_SUFFIX)?;
+    NaiveDate::parse_from_str(date, FILE_DATE_FORMAT).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn make_log(name: &str, retention_days: u32) -> (AuditLog, PathBuf) {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("audit_test_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        (AuditLog::new(dir.clone(), retention_days).unwrap(), dir)
+    }
+
+    fn make_record(time: &str, client: &str, resource: &str, cost: usize) -> AuditRecord {
+        AuditRecord {
+            time: DateTime::from_str(time).unwrap(),
+            request_id: String::from("abc"),
+            client: client.to_string(),
+            route: String::from("/v1/search/video"),
+            resource: resource.to_string(),
+            cost,
+            key: String::from("key0"),
+            status: Some(200),
+            outcome: String::from("ok"),
+            latency_ms: 12,
+        }
+    }
+
+    #[test]
+    fn test_records_are_split_by_day_and_read_back() {
+        //GIVEN an empty log
+        let (log, dir) = make_log("read", 400);
+        //WHEN recording calls across two days
+        log.record(&make_record("2020-06-01T10:00:00Z", "web", "search", 100)).unwrap();
+        log.record(&make_record("2020-06-01T23:59:59Z", "web", "videos", 1)).unwrap();
+        log.record(&make_record("2020-06-02T00:00:00Z", "app", "search", 100)).unwrap();
+        let all = log.read(DateTime::from_str("2020-06-01T00:00:00Z").unwrap(), DateTime::from_str("2020-06-30T00:00:00Z").unwrap()).unwrap();
+        let first_day = log.read(DateTime::from_str("2020-06-01T00:00:00Z").unwrap(), DateTime::from_str("2020-06-01T23:59:59Z").unwrap()).unwrap();
+        let file_count = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        //THEN there's a file per day and reads are limited to the window
+        assert_eq!(file_count, 2);
+        assert_eq!(all.len(), 3);
+        assert_eq!(first_day.len(), 2);
+        assert_eq!(first_day[1].resource, "videos");
+    }
+
+    #[test]
+    fn test_expired_files_are_removed_on_rotation() {
+        //GIVEN a log keeping 30 days
+        let (log, dir) = make_log("retention", 30);
+        //WHEN recording calls 31 days apart
+        log.record(&make_record("2020-05-01T10:00:00Z", "web", "search", 100)).unwrap();
+        log.record(&make_record("2020-05-31T10:00:00Z", "web", "search", 100)).unwrap();
+        log.record(&make_record("2020-06-01T10:00:00Z", "web", "search", 100)).unwrap();
+        let mut dates: Vec<NaiveDate> = fs::read_dir(&dir).unwrap().map(|entry| file_date(&entry.unwrap().path()).unwrap()).collect();
+        dates.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        //THEN only the oldest file was removed
+        assert_eq!(dates, vec![NaiveDate::from_ymd_opt(2020, 5, 31).unwrap(), NaiveDate::from_ymd_opt(2020, 6, 1).unwrap()]);
+    }
+
+    #[test]
+    fn test_spend_by_day_and_month() {
+        //GIVEN calls by two clients over two months and a partial line
+        let (log, dir) = make_log("spend", 400);
+        log.record(&make_record("2020-05-31T10:00:00Z", "web", "search", 100)).unwrap();
+        log.record(&make_record("2020-06-01T10:00:00Z", "web", "search", 100)).unwrap();
+        log.record(&make_record("2020-06-01T11:00:00Z", "app", "videos", 1)).unwrap();
+        log.record(&make_record("2020-06-03T11:00:00Z", "app", "videos", 1)).unwrap();
+        fs::OpenOptions::new().append(true).open(dir.join("audit-2020-06-03.jsonl")).unwrap().write_all(b"{\"time\":").unwrap();
+        let from = DateTime::from_str("2020-05-01T00:00:00Z").unwrap();
+        let to = DateTime::from_str("2020-06-30T23:59:59Z").unwrap();
+        //WHEN grouping by day and by month
+        let daily = log.spend(from, to, Period::Day).unwrap();
+        let monthly = log.spend(from, to, Period::Month).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        //THEN costs are summed per period and client
+        assert_eq!(daily.get_cost(), 202);
+        let days: Vec<&str> = daily.get_periods().iter().map(|period| period.get_period()).collect();
+        assert_eq!(days, vec!["2020-05-31", "2020-06-01", "2020-06-03"]);
+        assert_eq!(monthly.get_periods().len(), 2);
+        let june = &monthly.get_periods()[1];
+        assert_eq!(june.get_period(), "2020-06");
+        assert_eq!(june.get_cost(), 102);
+        assert_eq!(june.get_by_client().get("web"), Some(&100));
+        assert_eq!(june.get_by_client().get("app"), Some(&2));
+    }
+}
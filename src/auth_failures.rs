@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::error::ApiError;
+use crate::logger;
+
+const WINDOW: u64 = 5 * 60;
+const MAX_FAILURES: u32 = 10;
+/// Failures are logged every time an address reaches a multiple of this
+const LOG_EVERY: u32 = 5;
+
+struct Failures {
+    window_start: Instant,
+    count: u32,
+}
+
+/// Counts failed authentication attempts per address so keys can't be guessed quickly
+pub struct AuthFailures {
+    failures: Mutex<HashMap<IpAddr, Failures>>,
+}
+
+impl AuthFailures {
+    pub fn new() -> AuthFailures {
+        AuthFailures {
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl AuthFailures {
+    /// Fails if `ip` has had too many failures recently
+    pub fn check(&self, ip: IpAddr) -> Result<(), ApiError> {
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, entry| entry.window_start.elapsed() < Duration::from_secs(WINDOW));
+        match failures.get(&ip) {
+            Some(entry) if entry.count >= MAX_FAILURES => {
+                let retry_after = WINDOW.saturating_sub(entry.window_start.elapsed().as_secs()).max(1);
+                Err(ApiError::RateLimited(String::from("Too many failed authentication attempts"), retry_after))
+            }
+            _ => Ok(())
+        }
+    }
+
+    pub fn record(&self, ip: IpAddr) {
+        let mut failures = self.failures.lock().unwrap();
+        let entry = failures.entry(ip).or_insert(Failures { window_start: Instant::now(), count: 0 });
+        entry.count += 1;
+        if entry.count.is_multiple_of(LOG_EVERY) {
+            logger::warn(None, "Repeated authentication failures", &[
+                ("ip", ip.to_string()),
+                ("failures", entry.count.to_string()),
+                ("seconds", entry.window_start.elapsed().as_secs().to_string())]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_address_is_blocked_after_max_failures() {
+        //GIVEN two addresses
+        let failures = AuthFailures::new();
+        let bad: IpAddr = "10.0.0.1".parse().unwrap();
+        let good: IpAddr = "10.0.0.2".parse().unwrap();
+        //WHEN one fails the maximum number of times
+        for _ in 0..MAX_FAILURES {
+            assert!(failures.check(bad).is_ok());
+            failures.record(bad);
+        }
+        //THEN only that one is blocked
+        assert!(matches!(failures.check(bad), Err(ApiError::RateLimited(_, _))));
+        assert!(failures.check(good).is_ok());
+    }
+}
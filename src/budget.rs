@@ -0,0 +1,132 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use rocket::Request;
+use rocket::http::Header;
+use rocket::response::{self, Responder};
+use crate::error::ApiError;
+
+const TIME_HEADER: &str = "x-budget-ms";
+const QUOTA_HEADER: &str = "x-budget-quota";
+const TIME_PARAM: &str = "budget_ms";
+const QUOTA_PARAM: &str = "budget_quota";
+pub const CURSOR_HEADER: &str = "x-continuation-cursor";
+
+/// Limit on how long a request can keep calling YouTube and how much quota it can spend,
+/// quota is checked before each call and a call still running at the deadline is abandoned
+pub struct Budget {
+    deadline: Option<Instant>,
+    quota: Option<usize>,
+    spent: AtomicUsize,
+}
+
+impl Budget {
+    pub fn new(time: Option<Duration>, quota: Option<usize>) -> Budget {
+        Budget {
+            deadline: time.map(|time| Instant::now() + time),
+            quota,
+            spent: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn unlimited() -> Budget {
+        Budget::new(None, None)
+    }
+
+    /// From the headers, or query params if a header isn't set
+    pub fn from_request(request: &Request) -> Result<Budget, ApiError> {
+        let time = budget_value(request, TIME_HEADER, TIME_PARAM)?;
+        let quota = budget_value(request, QUOTA_HEADER, QUOTA_PARAM)?;
+        Ok(Budget::new(time.map(Duration::from_millis), quota.map(|quota| quota as usize)))
+    }
+}
+
+impl Budget {
+    pub fn reserve(&self, cost: usize) -> Result<(), ApiError> {
+        self.check_deadline()?;
+        match self.quota {
+            Some(quota) => self.spent
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |spent| Some(spent + cost).filter(|total| *total <= quota))
+                .map(|_| ())
+                .map_err(|_| ApiError::BudgetExhausted(format!("Request quota budget of {} used", quota))),
+            None => Ok(())
+        }
+    }
+
+    /// Time left before the deadline, None if there isn't one
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn check_deadline(&self) -> Result<(), ApiError> {
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(ApiError::BudgetExhausted(String::from("Request time budget used"))),
+            _ => Ok(())
+        }
+    }
+
+    pub fn refund(&self, cost: usize) {
+        let _ = self.spent.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |spent| Some(spent.saturating_sub(cost)));
+    }
+}
+
+fn budget_value(request: &Request, header: &str, param: &str) -> Result<Option<u64>, ApiError> {
+    let value = request.headers().get_one(header)
+        .map(|value| value.to_string())
+        .or_else(|| request.query_value::<String>(param).and_then(|value| value.ok()));
+    match value {
+        None => Ok(None),
+        Some(value) => match value.trim().parse::<u64>() {
+            Ok(value) if value > 0 => Ok(Some(value)),
+            _ => Err(ApiError::BadInput(format!("{} must be a whole number above 0", param)))
+        }
+    }
+}
+
+/// `value` with the cursor to continue from in [CURSOR_HEADER] if it was cut short by the budget
+pub struct Partial<T> {
+    value: T,
+    cursor: Option<String>,
+}
+
+impl<T> Partial<T> {
+    pub fn new(value: T, cursor: Option<String>) -> Self {
+        Partial { value, cursor }
+    }
+}
+
+impl<'r, 'o: 'r, T: Responder<'r, 'o>> Responder<'r, 'o> for Partial<T> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let mut response = self.value.respond_to(request)?;
+        if let Some(cursor) = self.cursor {
+            response.set_header(Header::new(CURSOR_HEADER, cursor));
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quota_budget() {
+        //GIVEN a budget of 3 quota
+        let budget = Budget::new(None, Some(3));
+        //WHEN reserving more than that
+        //THEN the call that would go over is refused
+        assert!(budget.reserve(1).is_ok());
+        assert!(budget.reserve(2).is_ok());
+        assert_eq!(budget.reserve(1).unwrap_err().get_code(), "budget_exhausted");
+    }
+
+    #[test]
+    fn test_time_budget() {
+        //GIVEN a budget that's already run out
+        let budget = Budget::new(Some(Duration::from_millis(0)), None);
+        let unlimited = Budget::unlimited();
+        //WHEN reserving
+        //THEN only the unlimited budget allows it
+        assert!(budget.reserve(1).is_err());
+        assert!(unlimited.reserve(10_000).is_ok());
+    }
+}
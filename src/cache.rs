@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use crate::metrics::METRICS;
+
+/// Keys come from request parameters, so without a cap the cache could grow without bound
+const MAX_ENTRIES: usize = 1000;
+
+pub struct Cache<K, V> {
+    /// Used to label metrics
+    name: &'static str,
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Cache<K, V> {
+    pub fn new(name: &'static str, ttl: Duration) -> Cache<K, V> {
+        Cache {
+            name,
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Cache<K, V> {
+    pub fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(key)
+            .filter(|(inserted, _)| inserted.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Expired entries are dropped first, then the oldest if the cache is still full
+    pub fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.contains_key(&key) && entries.len() >= MAX_ENTRIES {
+            let ttl = self.ttl;
+            entries.retain(|_, (inserted, _)| inserted.elapsed() < ttl);
+            if entries.len() >= MAX_ENTRIES {
+                let oldest = entries.iter().min_by_key(|(_, (inserted, _))| *inserted).map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        entries.insert(key, (Instant::now(), value));
+    }
+
+    /// Returns the cached value for `key` or, if missing or expired, calls `fetch` and caches the result
+    /// The lock is not held while fetching so slow upstream calls don't block other readers
+    pub async fn get_or_fetch<F: FnOnce() -> R, R: Future<Output = Result<V>>>(&self, key: K, fetch: F) -> Result<V> {
+        if let Some(value) = self.get(&key) {
+            METRICS.record_cache(self.name, true);
+            return Ok(value);
+        }
+        METRICS.record_cache(self.name, false);
+        let value = fetch().await?;
+        self.insert(key, value.clone());
+        Ok(value)
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cached_value_is_returned() {
+        //GIVEN cache with a value
+        let cache: Cache<&str, usize> = Cache::new("test", Duration::from_secs(60));
+        cache.insert("key", 1);
+        //WHEN value is fetched
+        let value = cache.get_or_fetch("key", || async { Ok(2) }).await.unwrap();
+        //THEN cached value is returned
+        assert_eq!(value, 1);
+    }
+
+    #[tokio::test]
+    async fn test_expired_value_is_refetched() {
+        //GIVEN cache with no ttl and a value
+        let cache: Cache<&str, usize> = Cache::new("test", Duration::from_secs(0));
+        cache.insert("key", 1);
+        //WHEN value is fetched
+        let value = cache.get_or_fetch("key", || async { Ok(2) }).await.unwrap();
+        //THEN new value is returned
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn test_clear() {
+        //GIVEN cache with a value
+        let cache: Cache<&str, usize> = Cache::new("test", Duration::from_secs(60));
+        cache.insert("key", 1);
+        //WHEN cache is cleared
+        cache.clear();
+        //THEN value is gone
+        assert!(cache.get(&"key").is_none());
+    }
+
+    #[test]
+    fn test_expired_entries_are_pruned_on_insert() {
+        //GIVEN full cache with no ttl
+        let cache: Cache<usize, usize> = Cache::new("test", Duration::from_secs(0));
+        for key in 0..MAX_ENTRIES {
+            cache.insert(key, key);
+        }
+        //WHEN another value is inserted
+        cache.insert(MAX_ENTRIES, 0);
+        //THEN the expired entries are gone
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_oldest_entry_is_evicted_when_full() {
+        //GIVEN full cache of live entries
+        let cache: Cache<usize, usize> = Cache::new("test", Duration::from_secs(60));
+        cache.insert(0, 0);
+        std::thread::sleep(Duration::from_millis(5));
+        for key in 1..MAX_ENTRIES {
+            cache.insert(key, key);
+        }
+        //WHEN another value is inserted
+        cache.insert(MAX_ENTRIES, 0);
+        //THEN the size is capped and the first entry made way
+        assert_eq!(cache.entries.lock().unwrap().len(), MAX_ENTRIES);
+        assert!(cache.get(&0).is_none());
+        assert!(cache.get(&MAX_ENTRIES).is_some());
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::{bail, Context, Result};
+use reqwest::{Client, Method};
+use structopt::StructOpt;
+use std::env;
+use crate::config::{Args, Config};
+use crate::request_context::RequestContext;
+use crate::make_youtube_manager;
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Run the server, the default if no command is given
+    Serve,
+    /// Check the YouTube key pool
+    Keys(KeysCommand),
+    /// Manage the running server's quotas
+    Quota(QuotaCommand),
+    /// Manage the running server's caches
+    Cache(CacheCommand),
+    /// Print the JSON the API would return for a video, channel or playlist
+    Fetch(FetchCommand),
+}
+
+#[derive(Debug, StructOpt)]
+pub enum KeysCommand {
+    /// Remaining quota and disabled state of each key in the running server
+    Status,
+    /// Make a cheap call with each key and report whether it works
+    Test,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum QuotaCommand {
+    /// Reset key and client quotas in the running server
+    Reset,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum CacheCommand {
+    /// Empty the running server's caches
+    Purge,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum FetchCommand {
+    Video { id: String },
+    Channel { id: String },
+    Playlist { id: String },
+}
+
+/// Commands that change or read quotas and caches go through the running server's admin API,
+/// as that state only exists in its memory. The others use their own [YoutubeManager]
+pub async fn run(command: Command, config: &Config, args: &Args) -> Result<()> {
+    match command {
+        Command::Serve => unreachable!("serve is handled by main"),
+        Command::Keys(KeysCommand::Status) => println!("{}", admin_request(Method::GET, "/v1/admin/status", config, args).await?),
+        Command::Keys(KeysCommand::Test) => {
+            let results = make_youtube_manager(config)?.test_keys().await;
+            println!("{}", serde_json::to_string_pretty(&results)?);
+            let failed = results.iter().filter(|result| !result.is_ok()).count();
+            if failed > 0 {
+                bail!("{} of {} keys failed", failed, results.len());
+            }
+        }
+        Command::Quota(QuotaCommand::Reset) => {
+            admin_request(Method::POST, "/v1/admin/quotas/reset", config, args).await?;
+            println!("Quotas reset");
+        }
+        Command::Cache(CacheCommand::Purge) => {
+            admin_request(Method::POST, "/v1/admin/cache/purge", config, args).await?;
+            println!("Caches purged");
+        }
+        Command::Fetch(fetch) => {
+            let youtube_manager = make_youtube_manager(config)?;
+            let ctx = RequestContext::system();
+            let json = match fetch {
+                FetchCommand::Video { id } => youtube_manager.single_video(&ctx, id.clone()).await?
+                    .map(|video| serde_json::to_string(&video)).transpose()?
+                    .with_context(|| format!("Video {} not found", id))?,
+                FetchCommand::Channel { id } => youtube_manager.single_channel(&ctx, id.clone()).await?
+                    .map(|channel| serde_json::to_string(&channel)).transpose()?
+                    .with_context(|| format!("Channel {} not found", id))?,
+                FetchCommand::Playlist { id } => youtube_manager.single_playlist(&ctx, id.clone()).await?
+                    .map(|playlist| serde_json::to_string(&playlist)).transpose()?
+                    .with_context(|| format!("Playlist {} not found", id))?,
+            };
+            println!("{}", json);
+        }
+    }
+    Ok(())
+}
+
+/// `--server`, or the configured port on this machine
+fn server_url(config: &Config, args: &Args) -> String {
+    if let Some(server) = &args.server {
+        return server.trim_end_matches('/').to_string();
+    }
+    let host = if config.server.address == "0.0.0.0" { "127.0.0.1" } else { config.server.address.as_str() };
+    format!("http://{}:{}", host, config.server.port)
+}
+
+/// `--admin-key`, then `ADMIN_API_KEY`, then the configured `api_key`
+fn admin_key(config: &Config, args: &Args) -> Option<String> {
+    args.admin_key.clone()
+        .or(env::var("ADMIN_API_KEY").ok())
+        .or(config.auth.api_key.clone())
+}
+
+async fn admin_request(method: Method, path: &str, config: &Config, args: &Args) -> Result<String> {
+    let server = server_url(config, args);
+    let mut request = Client::new().request(method, format!("{}{}", server, path));
+    if let Some(key) = admin_key(config, args) {
+        request = request.header("x-api-key", key);
+    }
+    let response = request.send().await.with_context(|| format!("Unable to reach the server at {}", server))?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        bail!("{} returned {}: {}", path, status.as_u16(), body);
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_commands_are_parsed() {
+        let args = Args::from_iter(&["youtube-proxy-server", "--port", "4000", "fetch", "video", "abc"]);
+        assert_eq!(args.port, Some(4000));
+        match args.command {
+            Some(Command::Fetch(FetchCommand::Video { id })) => assert_eq!(id, "abc"),
+            other => panic!("Unexpected command {:?}", other)
+        }
+        let args = Args::from_iter(&["youtube-proxy-server"]);
+        assert!(args.command.is_none());
+    }
+
+    #[test]
+    fn test_server_url() {
+        //GIVEN default config listening on all interfaces
+        let config = Config::default();
+        let args = Args::default();
+        let explicit = Args { server: Some(String::from("https://proxy.example.com/")), ..Args::default() };
+        //WHEN the url is worked out
+        //THEN it's local unless given
+        assert_eq!(server_url(&config, &args), "http://127.0.0.1:3001");
+        assert_eq!(server_url(&config, &explicit), "https://proxy.example.com");
+    }
+}
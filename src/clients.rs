@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use anyhow::{Result, Context};
+use serde::{Serialize, Deserialize};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Mutex as AsyncMutex;
+use std::net::IpAddr;
+use crate::error::ApiError;
+use crate::key_hash::KeyHasher;
+use crate::auth_failures::AuthFailures;
+use crate::tokens::TokenVerifier;
+use crate::logger;
+use crate::timer::seconds_until_reset;
+
+const RATE_LIMIT_WINDOW: u64 = 60;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Single items, channel/playlist listings, trending, meta and chat
+    Read,
+    /// Search endpoints, these cost 100x more quota than everything else
+    Search,
+    /// Everything under /v1/admin
+    Admin,
+}
+
+impl Scope {
+    pub fn all() -> Vec<Scope> {
+        vec![Scope::Read, Scope::Search, Scope::Admin]
+    }
+}
+
+fn default_scopes() -> Vec<Scope> {
+    vec![Scope::Read, Scope::Search]
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientEntry {
+    pub name: String,
+    /// Plaintext key, only accepted when adding a client and replaced by `key_hash` before anything is stored
+    #[serde(default, skip_serializing)]
+    pub key: Option<String>,
+    /// See [KeyHasher::hash]
+    #[serde(default)]
+    pub key_hash: String,
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<Scope>,
+    /// Quota units this client can spend per day, unlimited if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_budget: Option<usize>,
+    /// Requests per minute, unlimited if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientStatus {
+    scopes: Vec<Scope>,
+    spent: usize,
+    requests: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    daily_budget: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remaining: Option<usize>,
+}
+
+#[derive(Clone)]
+struct ClientUsage {
+    spent: usize,
+    requests: usize,
+    window_start: Instant,
+    window_requests: u32,
+}
+
+/// Budget and rate limit for one client, shared between all of its in flight requests
+pub struct ClientQuota {
+    entry: ClientEntry,
+    usage: Mutex<ClientUsage>,
+}
+
+impl ClientQuota {
+    fn new(entry: ClientEntry) -> ClientQuota {
+        ClientQuota {
+            entry,
+            usage: Mutex::new(ClientUsage {
+                spent: 0,
+                requests: 0,
+                window_start: Instant::now(),
+                window_requests: 0,
+            }),
+        }
+    }
+}
+
+impl ClientQuota {
+    pub fn get_name(&self) -> &str {
+        &self.entry.name
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.entry.scopes.contains(&scope)
+    }
+
+    /// Counts an incoming request against the rate limit
+    pub fn check_rate(&self) -> Result<(), ApiError> {
+        let mut usage = self.usage.lock().unwrap();
+        if usage.window_start.elapsed() >= Duration::from_secs(RATE_LIMIT_WINDOW) {
+            usage.window_start = Instant::now();
+            usage.window_requests = 0;
+        }
+        if let Some(limit) = self.entry.rate_limit {
+            if usage.window_requests >= limit {
+                let retry_after = RATE_LIMIT_WINDOW.saturating_sub(usage.window_start.elapsed().as_secs()).max(1);
+                return Err(ApiError::RateLimited(format!("Rate limit of {} requests per minute reached", limit), retry_after));
+            }
+        }
+        usage.window_requests += 1;
+        usage.requests += 1;
+        Ok(())
+    }
+
+    /// Takes `cost` from the daily budget, must be called before a YouTube key is used
+    pub fn reserve(&self, cost: usize) -> Result<(), ApiError> {
+        let mut usage = self.usage.lock().unwrap();
+        if let Some(budget) = self.entry.daily_budget {
+            if usage.spent + cost > budget {
+                return Err(ApiError::ClientQuotaExhausted(format!("Daily budget of {} used", budget), seconds_until_reset()));
+            }
+        }
+        usage.spent += cost;
+        Ok(())
+    }
+
+    /// Gives back a [ClientQuota::reserve] for a call that wasn't made or failed
+    pub fn refund(&self, cost: usize) {
+        let mut usage = self.usage.lock().unwrap();
+        usage.spent = usage.spent.saturating_sub(cost);
+    }
+
+    fn reset(&self) {
+        let mut usage = self.usage.lock().unwrap();
+        usage.spent = 0;
+        usage.requests = 0;
+    }
+
+    fn get_status(&self) -> ClientStatus {
+        let usage = self.usage.lock().unwrap();
+        ClientStatus {
+            scopes: self.entry.scopes.clone(),
+            spent: usage.spent,
+            requests: usage.requests,
+            daily_budget: self.entry.daily_budget,
+            remaining: self.entry.daily_budget.map(|budget| budget.saturating_sub(usage.spent)),
+        }
+    }
+}
+
+/// Clients allowed to use the server, if there are none the server is open to everyone
+/// Changes made through the admin endpoints are written to `path` and it's reread on SIGHUP
+pub struct ClientRegistry {
+    clients: RwLock<Vec<Arc<ClientQuota>>>,
+    /// Clients authenticated by token, keyed by the token's `sub` so usage carries over between requests
+    token_clients: RwLock<HashMap<String, Arc<ClientQuota>>>,
+    path: Option<PathBuf>,
+    hasher: KeyHasher,
+    failures: AuthFailures,
+    tokens: Option<TokenVerifier>,
+    /// See [ClientRegistry::lockout_address]
+    trusted_proxies: Vec<IpAddr>,
+    /// Serialises client file writes
+    saving: AsyncMutex<()>,
+}
+
+impl ClientRegistry {
+    /// Any plaintext keys in `entries` are hashed, and the file rewritten without them
+    pub fn new(entries: Vec<ClientEntry>, path: Option<PathBuf>, hasher: KeyHasher) -> ClientRegistry {
+        let registry = ClientRegistry {
+            clients: RwLock::new(vec![]),
+            token_clients: RwLock::new(HashMap::new()),
+            path,
+            hasher,
+            failures: AuthFailures::new(),
+            tokens: None,
+            trusted_proxies: vec![],
+            saving: AsyncMutex::new(()),
+        };
+        if registry.replace_clients(entries) {
+            // at startup, before any requests are served
+            if let Err(error) = registry.path.as_ref().map_or(Ok(()), |path| write_entries(path, &registry.get_entries())) {
+                logger::error(None, "Unable to save hashed client keys", &[("error", format!("{:?}", error))]);
+            }
+        }
+        registry
+    }
+
+    pub fn load(path: &PathBuf) -> Result<Vec<ClientEntry>> {
+        let json = fs::read_to_string(path).with_context(|| format!("Unable to read {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("Invalid client file {}", path.display()))
+    }
+
+    #[cfg(unix)]
+    fn load_if_exists(path: &PathBuf) -> Result<Option<Vec<ClientEntry>>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        ClientRegistry::load(path).map(Some)
+    }
+}
+
+impl ClientRegistry {
+    /// Also accept bearer tokens, see [TokenVerifier]
+    pub fn set_token_verifier(&mut self, verifier: TokenVerifier) {
+        self.tokens = Some(verifier);
+    }
+
+    pub fn set_trusted_proxies(&mut self, proxies: Vec<IpAddr>) {
+        self.trusted_proxies = proxies;
+    }
+
+    /// Address failed attempts are counted against, `client_ip` comes from a header so is only used if `remote` is a trusted proxy
+    pub fn lockout_address(&self, remote: Option<IpAddr>, client_ip: Option<IpAddr>) -> Option<IpAddr> {
+        match remote {
+            Some(remote) if self.trusted_proxies.contains(&remote) => client_ip.or(Some(remote)),
+            _ => remote
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.tokens.is_none() && self.clients.read().unwrap().is_empty()
+    }
+
+    /// Checks every client so the time taken doesn't reveal which client the key belongs to
+    pub fn find(&self, key: &str) -> Option<Arc<ClientQuota>> {
+        let mut found = None;
+        for client in self.clients.read().unwrap().iter() {
+            if self.hasher.verify(key, &client.entry.key_hash) {
+                found = Some(client.clone());
+            }
+        }
+        found
+    }
+
+    /// Checks the bearer token if there is one, otherwise the key
+    /// Failures are counted against `ip`, which is refused once it has failed too often
+    pub fn authenticate(&self, key: Option<&str>, token: Option<&str>, ip: Option<IpAddr>) -> Result<Arc<ClientQuota>, ApiError> {
+        if let Some(ip) = ip {
+            self.failures.check(ip)?;
+        }
+        let client = match token {
+            Some(token) => self.verify_token(token),
+            None => key.and_then(|key| self.find(key))
+        };
+        match client {
+            Some(client) => Ok(client),
+            None => {
+                if let Some(ip) = ip {
+                    self.failures.record(ip);
+                }
+                Err(ApiError::Unauthorized(String::from("Missing or invalid x-api-key or token")))
+            }
+        }
+    }
+
+    /// Token clients have no budget or rate limit, the backend minting them is expected to handle that
+    /// If a newer token for the same `sub` has different scopes they replace the old ones, usage is kept
+    fn verify_token(&self, token: &str) -> Option<Arc<ClientQuota>> {
+        let claims = self.tokens.as_ref()?.verify(token)?;
+        if let Some(client) = self.token_clients.read().unwrap().get(&claims.sub) {
+            if client.entry.scopes == claims.scopes {
+                return Some(client.clone());
+            }
+        }
+        let mut token_clients = self.token_clients.write().unwrap();
+        let entry = ClientEntry {
+            name: claims.sub.clone(),
+            key: None,
+            key_hash: String::new(),
+            scopes: claims.scopes,
+            daily_budget: None,
+            rate_limit: None,
+        };
+        let quota = ClientQuota::new(entry);
+        if let Some(previous) = token_clients.get(&claims.sub) {
+            *quota.usage.lock().unwrap() = previous.usage.lock().unwrap().clone();
+        }
+        let client = Arc::new(quota);
+        token_clients.insert(claims.sub, client.clone());
+        Some(client)
+    }
+
+    /// Returns false if a client with the same name or key already exists
+    pub async fn add_client(&self, mut entry: ClientEntry) -> Result<bool> {
+        let key = entry.key.take().unwrap_or_default();
+        if self.find(&key).is_some() {
+            return Ok(false);
+        }
+        entry.key_hash = self.hasher.hash(&key);
+        {
+            let mut clients = self.clients.write().unwrap();
+            if clients.iter().any(|client| client.entry.name == entry.name) {
+                return Ok(false);
+            }
+            clients.push(Arc::new(ClientQuota::new(entry)));
+        }
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// Returns false if there's no client called `name`
+    pub async fn revoke_client(&self, name: &str) -> Result<bool> {
+        {
+            let mut clients = self.clients.write().unwrap();
+            let count = clients.len();
+            clients.retain(|client| client.entry.name != name);
+            if clients.len() == count {
+                return Ok(false);
+            }
+        }
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// Usage is kept for clients that are still present
+    /// Returns true if any plaintext keys were hashed, so the file should be rewritten without them
+    fn replace_clients(&self, entries: Vec<ClientEntry>) -> bool {
+        let mut clients = self.clients.write().unwrap();
+        let mut hashed = false;
+        let replacements = entries.into_iter()
+            .map(|mut entry| {
+                if let Some(key) = entry.key.take() {
+                    entry.key_hash = self.hasher.hash(&key);
+                    hashed = true;
+                }
+                let usage = clients.iter()
+                    .find(|client| client.entry.name == entry.name)
+                    .map(|client| client.usage.lock().unwrap().clone());
+                let quota = ClientQuota::new(entry);
+                if let Some(usage) = usage {
+                    *quota.usage.lock().unwrap() = usage;
+                }
+                Arc::new(quota)
+            })
+            .collect();
+        *clients = replacements;
+        hashed
+    }
+
+    fn get_entries(&self) -> Vec<ClientEntry> {
+        self.clients.read().unwrap().iter().map(|client| client.entry.clone()).collect()
+    }
+
+    /// Writes the clients as they are once the previous save finishes, off the async workers and without holding the registry lock
+    async fn save(&self) -> Result<()> {
+        if let Some(path) = &self.path {
+            let _saving = self.saving.lock().await;
+            let entries = self.get_entries();
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || write_entries(&path, &entries)).await??;
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    pub fn start_reload_on_hangup(registry: Arc<ClientRegistry>) -> Result<()> {
+        let path = match &registry.path {
+            Some(path) => path.clone(),
+            None => return Ok(())
+        };
+        let mut hangups = signal(SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                let reading = path.clone();
+                let loaded = tokio::task::spawn_blocking(move || ClientRegistry::load_if_exists(&reading)).await;
+                match loaded.map_err(anyhow::Error::from).and_then(|result| result) {
+                    Ok(None) => {}
+                    Ok(Some(entries)) => {
+                        logger::info(None, "Reloaded clients", &[("count", entries.len().to_string()), ("path", path.display().to_string())]);
+                        if registry.replace_clients(entries) {
+                            if let Err(error) = registry.save().await {
+                                logger::error(None, "Unable to save hashed client keys", &[("error", format!("{:?}", error))]);
+                            }
+                        }
+                    }
+                    Err(error) => logger::error(None, "Unable to reload clients", &[("error", format!("{:?}", error))])
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// There's no SIGHUP, the clients file is only read at startup
+    #[cfg(not(unix))]
+    pub fn start_reload_on_hangup(_registry: Arc<ClientRegistry>) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn reset(&self) {
+        self.clients.read().unwrap().iter().for_each(|client| client.reset());
+        self.token_clients.read().unwrap().values().for_each(|client| client.reset());
+    }
+
+    pub fn get_status(&self) -> HashMap<String, ClientStatus> {
+        let mut status: HashMap<String, ClientStatus> = self.token_clients.read().unwrap().values()
+            .map(|client| (client.entry.name.clone(), client.get_status()))
+            .collect();
+        // a key client with the same name as a token's subject wins
+        status.extend(self.clients.read().unwrap().iter().map(|client| (client.entry.name.clone(), client.get_status())));
+        status
+    }
+}
+
+/// Writes to a temporary file first so a crash can't leave a half written client file
+fn write_entries(path: &Path, entries: &[ClientEntry]) -> Result<()> {
+    let json = serde_json::to_string_pretty(entries)?;
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, json).with_context(|| format!("Unable to write {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, path).with_context(|| format!("Unable to write {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(daily_budget: Option<usize>, rate_limit: Option<u32>) -> ClientEntry {
+        ClientEntry { name: String::from("app"), key: Some(String::from("secret")), key_hash: String::new(), scopes: default_scopes(), daily_budget, rate_limit }
+    }
+
+    fn make_registry(entries: Vec<ClientEntry>) -> ClientRegistry {
+        ClientRegistry::new(entries, None, KeyHasher::new(String::from("pepper")))
+    }
+
+    #[test]
+    fn test_budget_is_enforced() {
+        //GIVEN client with a budget of 150
+        let registry = make_registry(vec![entry(Some(150), None)]);
+        let client = registry.find("secret").unwrap();
+        //WHEN spending past the budget
+        let first = client.reserve(100);
+        let second = client.reserve(100);
+        let third = client.reserve(50);
+        //THEN requests over the budget are rejected
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(ApiError::ClientQuotaExhausted(_, _))));
+        assert!(third.is_ok());
+        assert_eq!(registry.get_status()["app"].remaining, Some(0));
+    }
+
+    #[test]
+    fn test_reset_restores_budget() {
+        //GIVEN client that has used its budget
+        let registry = make_registry(vec![entry(Some(100), None)]);
+        let client = registry.find("secret").unwrap();
+        client.reserve(100).unwrap();
+        //WHEN budgets are reset
+        registry.reset();
+        //THEN client can spend again
+        assert!(client.reserve(100).is_ok());
+    }
+
+    #[test]
+    fn test_rate_limit_is_enforced() {
+        //GIVEN client limited to 2 requests per minute
+        let registry = make_registry(vec![entry(None, Some(2))]);
+        let client = registry.find("secret").unwrap();
+        //WHEN making 3 requests
+        let results: Vec<bool> = (0..3).map(|_| client.check_rate().is_ok()).collect();
+        //THEN last one is rejected
+        assert_eq!(results, vec![true, true, false]);
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let registry = make_registry(vec![entry(None, None)]);
+        assert!(registry.find("wrong").is_none());
+        assert!(!registry.is_open());
+    }
+
+    #[tokio::test]
+    async fn test_revoke_and_reload() {
+        //GIVEN registry with one client that has spent some of its budget
+        let registry = make_registry(vec![entry(Some(500), None)]);
+        registry.find("secret").unwrap().reserve(100).unwrap();
+        let other = ClientEntry { name: String::from("other"), key: Some(String::from("secret2")), key_hash: String::new(), scopes: Scope::all(), daily_budget: None, rate_limit: None };
+        //WHEN a client is added, the file is reloaded and the first client is revoked
+        let added = registry.add_client(other.clone()).await.unwrap();
+        let duplicate = registry.add_client(other.clone()).await.unwrap();
+        registry.replace_clients(vec![entry(Some(500), None), ClientEntry { key: Some(String::from("secret2")), ..other }]);
+        let spent = registry.get_status()["app"].spent;
+        let revoked = registry.revoke_client("app").await.unwrap();
+        let missing = registry.revoke_client("app").await.unwrap();
+        //THEN usage survives the reload and the revoked key no longer works
+        assert!(added);
+        assert!(!duplicate);
+        assert_eq!(spent, 100);
+        assert!(revoked);
+        assert!(!missing);
+        assert!(registry.find("secret").is_none());
+        assert!(registry.find("secret2").unwrap().has_scope(Scope::Admin));
+    }
+
+    #[test]
+    fn test_default_scopes() {
+        let entries: Vec<ClientEntry> = serde_json::from_str(r#"[{"name":"app","key":"secret"}]"#).unwrap();
+        assert_eq!(entries[0].scopes, vec![Scope::Read, Scope::Search]);
+    }
+
+    #[test]
+    fn test_keys_are_stored_hashed() {
+        //GIVEN registry loaded from plaintext keys
+        let registry = make_registry(vec![entry(None, None)]);
+        //WHEN reading back the stored entry
+        let clients = registry.clients.read().unwrap();
+        let stored = &clients[0].entry;
+        //THEN only the hash is kept and it isn't serialized with the key
+        assert!(stored.key.is_none());
+        assert!(stored.key_hash.starts_with("sha256$"));
+        assert!(!serde_json::to_string(stored).unwrap().contains("secret\""));
+    }
+
+    #[test]
+    fn test_authenticate_blocks_repeated_failures() {
+        //GIVEN registry with one client
+        let registry = make_registry(vec![entry(None, None)]);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        //WHEN the wrong key is tried many times
+        let failures: Vec<ApiError> = (0..12).filter_map(|_| registry.authenticate(Some("wrong"), None, Some(ip)).err()).collect();
+        //THEN address is eventually refused even with the right key
+        assert!(matches!(failures[0], ApiError::Unauthorized(_)));
+        assert!(matches!(failures[11], ApiError::RateLimited(_, _)));
+        assert!(registry.authenticate(Some("secret"), None, Some(ip)).is_err());
+        assert!(registry.authenticate(Some("secret"), None, None).is_ok());
+    }
+
+    #[test]
+    fn test_token_clients_are_kept() {
+        //GIVEN registry accepting tokens
+        let mut registry = make_registry(vec![]);
+        registry.set_token_verifier(TokenVerifier::hs256("jwt-secret", None, Some(String::from("proxy"))));
+        //WHEN the same subject makes two requests, then gets a token with more scopes
+        for _ in 0..2 {
+            let token = crate::tokens::test::make_token("jwt-secret", vec!["read"], "proxy", 60);
+            registry.authenticate(None, Some(&token), None).unwrap().check_rate().unwrap();
+        }
+        let token = crate::tokens::test::make_token("jwt-secret", vec!["read", "search"], "proxy", 60);
+        let upgraded = registry.authenticate(None, Some(&token), None).unwrap();
+        //THEN its requests are counted together and the new scopes apply
+        assert_eq!(registry.get_status()["frontend"].requests, 2);
+        assert!(upgraded.has_scope(Scope::Search));
+    }
+}
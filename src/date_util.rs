@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use chrono::{DateTime, Utc};
 use chrono::prelude::*;
 
@@ -25,22 +26,28 @@ impl DateExt for DateTime<Utc> {
             .with_nanosecond(999_999_999).unwrap()
     }
 
+    /// Moves back `count` calendar months, the day is clamped to the length of the target month
     fn minus_months(self, count: u32) -> Option<DateTime<Utc>> {
-        let years = count / 12;
-        let months = count - (years * 12);
-        self.with_year(self.year() - years as i32)?
-            .with_month(self.month() - months)
+        let total = (self.year() * 12 + self.month0() as i32).checked_sub(i32::try_from(count).ok()?)?;
+        let year = total.div_euclid(12);
+        let month = total.rem_euclid(12) as u32 + 1;
+        let day = self.day().min(days_in_month(month as u8, year as u32) as u32);
+        self.with_day(1)?
+            .with_year(year)?
+            .with_month(month)?
+            .with_day(day)
     }
 
     fn into_month_surround(self) -> (DateTime<Utc>, DateTime<Utc>) {
         let start = self.into_start_of_month();
-        let end = start.clone().into_end_of_month();
+        let end = start.into_end_of_month();
         (start, end)
     }
 }
 
-pub fn get_start_and_end_for_previous_month(from: DateTime<Utc>, month_count: u32) -> (DateTime<Utc>, DateTime<Utc>) {
-    from.minus_months(month_count).unwrap().into_month_surround()
+/// None if the month is out of range
+pub fn get_start_and_end_for_previous_month(from: DateTime<Utc>, month_count: u32) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    from.minus_months(month_count).map(|date| date.into_month_surround())
 }
 
 /// Returns days in a month (accounting for leap years)
@@ -61,7 +68,7 @@ pub fn days_in_month(month: u8, year: u32) -> u8 {
 }
 
 pub fn is_leap_year(year: u32) -> bool {
-    return (year % 4 == 0) && (year % 100 != 0 || year % 400 == 0);
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
 }
 
 #[cfg(test)]
@@ -100,9 +107,9 @@ mod test {
     fn test_start_of_month() {
         //GIVEN
         let test_date: DateTime<Utc> = DateTime::from_str("2020-01-01T20:30:12Z").unwrap();
-        let mut already_at_start = test_date.clone().into_start_of_month();
-        let mut fifth_day = test_date.clone().with_day(5).unwrap();
-        let mut end_of_month = test_date.clone().into_end_of_month();
+        let mut already_at_start = test_date.into_start_of_month();
+        let mut fifth_day = test_date.with_day(5).unwrap();
+        let mut end_of_month = test_date.into_end_of_month();
         let mut feb = DateTime::from_str("2020-02-22T04:34:45Z").unwrap();
 
         //WHEN
@@ -126,9 +133,9 @@ mod test {
     fn test_end_of_month() {
         //GIVEN
         let test_date: DateTime<Utc> = DateTime::from_str("2020-01-01T20:30:12Z").unwrap();
-        let mut start_of_month = test_date.clone().into_start_of_month();
-        let mut fifth_day = test_date.clone().with_day(5).unwrap();
-        let mut already_at_end = test_date.clone().into_end_of_month();
+        let mut start_of_month = test_date.into_start_of_month();
+        let mut fifth_day = test_date.with_day(5).unwrap();
+        let mut already_at_end = test_date.into_end_of_month();
         let mut feb = DateTime::from_str("2020-02-22T04:34:45Z").unwrap();
 
         //WHEN
@@ -148,6 +155,41 @@ mod test {
         assert_eq!(feb.day(), 29);
     }
 
+    #[test]
+    fn test_minus_months() {
+        //GIVEN dates at the start of a year and the end of a long month
+        let january: DateTime<Utc> = DateTime::from_str("2020-01-15T17:20:12Z").unwrap();
+        let march: DateTime<Utc> = DateTime::from_str("2020-03-31T17:20:12Z").unwrap();
+
+        //WHEN moving back
+        let december = january.minus_months(1).unwrap();
+        let two_years = january.minus_months(25).unwrap();
+        let february = march.minus_months(1).unwrap();
+        let same = march.minus_months(0).unwrap();
+
+        //THEN the year wraps and the day is clamped
+        assert_eq!((december.year(), december.month(), december.day()), (2019, 12, 15));
+        assert_eq!((two_years.year(), two_years.month(), two_years.day()), (2017, 12, 15));
+        assert_eq!((february.year(), february.month(), february.day()), (2020, 2, 29));
+        assert_eq!(same, march);
+        assert_eq!(december.hour(), 17);
+        assert!(january.minus_months(u32::MAX).is_none());
+        assert!(january.minus_months(i32::MAX as u32).is_none());
+    }
+
+    #[test]
+    fn test_start_end_for_previous_month() {
+        //GIVEN
+        let time: DateTime<Utc> = DateTime::from_str("2020-01-31T17:20:12Z").unwrap();
+
+        //WHEN
+        let (start, end) = get_start_and_end_for_previous_month(time, 2).unwrap();
+
+        //THEN
+        assert_eq!(start, DateTime::<Utc>::from_str("2019-11-01T00:00:00Z").unwrap());
+        assert_eq!((end.year(), end.month(), end.day(), end.hour()), (2019, 11, 30, 23));
+    }
+
     #[test]
     fn test_start_end_date_calculation_this_month() {
         //GIVEN
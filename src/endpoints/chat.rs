@@ -0,0 +1,17 @@
+use rocket::State;
+use rocket::http::ContentType;
+use rocket::response::stream::TextStream;
+use crate::youtube_manager::YoutubeManager;
+use crate::live_chat::ChatStream;
+use crate::error::ApiError;
+use crate::request_context::RequestContext;
+use crate::clients::Scope;
+
+#[get("/v1/video/<id>/chat/stream")]
+pub async fn stream(youtube_manager: &State<YoutubeManager>, id: String, ctx: RequestContext) -> Result<(ContentType, TextStream<ChatStream>), ApiError> {
+    ctx.require(Scope::Read)?;
+    match youtube_manager.live_chat_stream(&ctx, id).await? {
+        Some(stream) => Ok((ContentType::EventStream, TextStream(stream))),
+        None => Err(ApiError::NotFound(String::from("Video is not live")))
+    }
+}
@@ -0,0 +1,29 @@
+use rocket::State;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use std::sync::Arc;
+use crate::clients::{ClientEntry, ClientRegistry};
+use crate::error::ApiError;
+use crate::AdminKey;
+
+#[post("/v1/admin/clients", data = "<entry>")]
+pub async fn add(clients: &State<Arc<ClientRegistry>>, entry: Json<ClientEntry>, _admin_key: AdminKey) -> Result<Status, ApiError> {
+    let entry = entry.into_inner();
+    if entry.name.trim().is_empty() || entry.key.as_ref().is_none_or(|key| key.trim().is_empty()) {
+        return Err(ApiError::BadInput(String::from("name and key must not be empty")));
+    }
+    if clients.add_client(entry).await? {
+        Ok(Status::Created)
+    } else {
+        Err(ApiError::BadInput(String::from("Client name or key is already in use")))
+    }
+}
+
+#[delete("/v1/admin/clients/<name>")]
+pub async fn revoke(clients: &State<Arc<ClientRegistry>>, name: String, _admin_key: AdminKey) -> Result<Status, ApiError> {
+    if clients.revoke_client(&name).await? {
+        Ok(Status::Ok)
+    } else {
+        Err(ApiError::NotFound(format!("No client {}", name)))
+    }
+}
@@ -0,0 +1,59 @@
+use rocket::State;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use serde::Deserialize;
+use crate::youtube_manager::YoutubeManager;
+use crate::error::ApiError;
+use crate::AdminKey;
+
+#[derive(Deserialize)]
+pub struct NewKey {
+    key: String,
+    label: String,
+}
+
+#[derive(Deserialize)]
+pub struct KeyLabel {
+    label: String,
+}
+
+fn check_found(found: bool, idx: usize) -> Result<Status, ApiError> {
+    if found {
+        Ok(Status::Ok)
+    } else {
+        Err(ApiError::NotFound(format!("No key {}", idx)))
+    }
+}
+
+#[post("/v1/admin/keys", data = "<new_key>")]
+pub async fn add(youtube_manager: &State<YoutubeManager>, new_key: Json<NewKey>, _admin_key: AdminKey) -> Result<Status, ApiError> {
+    let new_key = new_key.into_inner();
+    if new_key.key.trim().is_empty() || new_key.label.trim().is_empty() {
+        return Err(ApiError::BadInput(String::from("key and label must not be empty")));
+    }
+    if youtube_manager.add_key(new_key.key, new_key.label).await? {
+        Ok(Status::Created)
+    } else {
+        Err(ApiError::BadInput(String::from("Key is already in the pool")))
+    }
+}
+
+#[delete("/v1/admin/keys/<idx>")]
+pub async fn remove(youtube_manager: &State<YoutubeManager>, idx: usize, _admin_key: AdminKey) -> Result<Status, ApiError> {
+    check_found(youtube_manager.remove_key(idx).await?, idx)
+}
+
+#[put("/v1/admin/keys/<idx>/label", data = "<label>")]
+pub async fn relabel(youtube_manager: &State<YoutubeManager>, idx: usize, label: Json<KeyLabel>, _admin_key: AdminKey) -> Result<Status, ApiError> {
+    check_found(youtube_manager.set_key_label(idx, label.into_inner().label).await?, idx)
+}
+
+#[post("/v1/admin/keys/<idx>/disable")]
+pub async fn disable(youtube_manager: &State<YoutubeManager>, idx: usize, _admin_key: AdminKey) -> Result<Status, ApiError> {
+    check_found(youtube_manager.disable_key(idx).await?, idx)
+}
+
+#[post("/v1/admin/keys/<idx>/enable")]
+pub async fn enable(youtube_manager: &State<YoutubeManager>, idx: usize, _admin_key: AdminKey) -> Result<Status, ApiError> {
+    check_found(youtube_manager.enable_key(idx).await?, idx)
+}
@@ -0,0 +1,27 @@
+use rocket::State;
+use crate::youtube_manager::{YoutubeManager, DEFAULT_REGION};
+use rocket::serde::json::Json;
+use crate::models::category::Category;
+use crate::models::region::Region;
+use crate::models::language::Language;
+use crate::error::ApiError;
+use crate::request_context::RequestContext;
+use crate::clients::Scope;
+
+#[get("/v1/meta/categories?<region>")]
+pub async fn categories(youtube_manager: &State<YoutubeManager>, region: Option<String>, ctx: RequestContext) -> Result<Json<Vec<Category>>, ApiError> {
+    ctx.require(Scope::Read)?;
+    Ok(Json(youtube_manager.categories(&ctx, region.unwrap_or(DEFAULT_REGION.to_string())).await?))
+}
+
+#[get("/v1/meta/regions")]
+pub async fn regions(youtube_manager: &State<YoutubeManager>, ctx: RequestContext) -> Result<Json<Vec<Region>>, ApiError> {
+    ctx.require(Scope::Read)?;
+    Ok(Json(youtube_manager.regions(&ctx).await?))
+}
+
+#[get("/v1/meta/languages")]
+pub async fn languages(youtube_manager: &State<YoutubeManager>, ctx: RequestContext) -> Result<Json<Vec<Language>>, ApiError> {
+    ctx.require(Scope::Read)?;
+    Ok(Json(youtube_manager.languages(&ctx).await?))
+}
@@ -1,3 +1,10 @@
 pub mod search;
 pub mod single;
-pub mod videos;
\ No newline at end of file
+pub mod videos;
+pub mod meta;
+pub mod chat;
+pub mod trending;
+pub mod keys;
+pub mod clients;
+pub mod spend;
+pub mod usage;
\ No newline at end of file
@@ -1,26 +1,38 @@
 use rocket::State;
 use crate::youtube_manager::YoutubeManager;
 use crate::models::channel::Channel;
-use rocket_contrib::json::Json;
-use anyhow::Result;
+use rocket::serde::json::Json;
 use crate::models::playlist::Playlist;
 use crate::models::video::Video;
-use crate::ApiKey;
+use crate::error::ApiError;
+use crate::request_context::RequestContext;
+use crate::clients::Scope;
+
+fn check_query(q: &str) -> Result<(), ApiError> {
+    if q.trim().is_empty() {
+        Err(ApiError::BadInput(String::from("q must not be empty")))
+    } else {
+        Ok(())
+    }
+}
 
 #[get("/v1/search/channel?<q>")]
-pub fn channel(youtube_manager: State<YoutubeManager>, q: String, _api_key: ApiKey) -> Result<Json<Vec<Channel>>> {
-    youtube_manager.search_channel(q)
-        .map(|list| Json(list))
+pub async fn channel(youtube_manager: &State<YoutubeManager>, q: String, ctx: RequestContext) -> Result<Json<Vec<Channel>>, ApiError> {
+    ctx.require(Scope::Search)?;
+    check_query(&q)?;
+    Ok(Json(youtube_manager.search_channel(&ctx, q).await?))
 }
 
 #[get("/v1/search/video?<q>")]
-pub fn video(youtube_manager: State<YoutubeManager>, q: String, _api_key: ApiKey) -> Result<Json<Vec<Video>>> {
-    youtube_manager.search_video( q)
-        .map(|list| Json(list))
+pub async fn video(youtube_manager: &State<YoutubeManager>, q: String, ctx: RequestContext) -> Result<Json<Vec<Video>>, ApiError> {
+    ctx.require(Scope::Search)?;
+    check_query(&q)?;
+    Ok(Json(youtube_manager.search_video(&ctx, q).await?))
 }
 
 #[get("/v1/search/playlist?<q>")]
-pub fn playlist(youtube_manager: State<YoutubeManager>, q: String, _api_key: ApiKey) -> Result<Json<Vec<Playlist>>> {
-    youtube_manager.search_playlist(q)
-        .map(|list| Json(list))
-}
\ No newline at end of file
+pub async fn playlist(youtube_manager: &State<YoutubeManager>, q: String, ctx: RequestContext) -> Result<Json<Vec<Playlist>>, ApiError> {
+    ctx.require(Scope::Search)?;
+    check_query(&q)?;
+    Ok(Json(youtube_manager.search_playlist(&ctx, q).await?))
+}
@@ -1,39 +1,38 @@
 use rocket::State;
 use crate::youtube_manager::YoutubeManager;
 use crate::models::channel::Channel;
-use rocket_contrib::json::Json;
+use rocket::serde::json::Json;
 use anyhow::Result;
-use rocket::http::Status;
 use crate::models::playlist::Playlist;
 use crate::models::video::Video;
-use crate::ApiKey;
+use crate::error::ApiError;
+use crate::request_context::RequestContext;
+use crate::clients::Scope;
 
-fn process_single_result<T>(result: Result<Option<T>>) -> Result<Json<T>, Status> {
-    if let Err(error) = result {
-        eprintln!("{:?}", error);
-        return Err(Status::InternalServerError);
-    }
-    if let Some(item) = result.unwrap() {
-        Ok(Json(item))
-    } else {
-        Err(Status::NotFound)
+fn process_single_result<T>(result: Result<Option<T>>, name: &str) -> Result<Json<T>, ApiError> {
+    match result? {
+        Some(item) => Ok(Json(item)),
+        None => Err(ApiError::NotFound(format!("{} not found", name)))
     }
 }
 
 #[get("/v1/channel/<id>")]
-pub fn channel(youtube_manager: State<YoutubeManager>, id: String, _api_key: ApiKey) -> Result<Json<Channel>, Status> {
-    let channel = youtube_manager.single_channel(id);
-    process_single_result(channel)
+pub async fn channel(youtube_manager: &State<YoutubeManager>, id: String, ctx: RequestContext) -> Result<Json<Channel>, ApiError> {
+    ctx.require(Scope::Read)?;
+    let channel = youtube_manager.single_channel(&ctx, id).await;
+    process_single_result(channel, "Channel")
 }
 
 #[get("/v1/video/<id>")]
-pub fn video(youtube_manager: State<YoutubeManager>, id: String, _api_key: ApiKey) -> Result<Json<Video>, Status> {
-    let video = youtube_manager.single_video(id);
-    process_single_result(video)
+pub async fn video(youtube_manager: &State<YoutubeManager>, id: String, ctx: RequestContext) -> Result<Json<Video>, ApiError> {
+    ctx.require(Scope::Read)?;
+    let video = youtube_manager.single_video(&ctx, id).await;
+    process_single_result(video, "Video")
 }
 
 #[get("/v1/playlist/<id>")]
-pub fn playlist(youtube_manager: State<YoutubeManager>, id: String, _api_key: ApiKey) -> Result<Json<Playlist>, Status> {
-    let playlist = youtube_manager.single_playlist(id);
-    process_single_result(playlist)
-}
\ No newline at end of file
+pub async fn playlist(youtube_manager: &State<YoutubeManager>, id: String, ctx: RequestContext) -> Result<Json<Playlist>, ApiError> {
+    ctx.require(Scope::Read)?;
+    let playlist = youtube_manager.single_playlist(&ctx, id).await;
+    process_single_result(playlist, "Playlist")
+}
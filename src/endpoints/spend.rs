@@ -0,0 +1,33 @@
+use rocket::State;
+use rocket::serde::json::Json;
+use chrono::Utc;
+use crate::youtube_manager::YoutubeManager;
+use crate::models::spend::SpendReport;
+use crate::audit::Period;
+use crate::date_util::get_start_and_end_for_previous_month;
+use crate::error::ApiError;
+use crate::AdminKey;
+
+const MAX_MONTHS: u32 = 24;
+
+/// Spend for `months` calendar months, ending `months_back` months before the current one
+#[get("/v1/admin/spend?<by>&<months_back>&<months>")]
+pub async fn spend(youtube_manager: &State<YoutubeManager>, by: Option<String>, months_back: Option<u32>, months: Option<u32>, _admin_key: AdminKey) -> Result<Json<SpendReport>, ApiError> {
+    let period = match by {
+        Some(by) => Period::parse(&by).ok_or(ApiError::BadInput(String::from("by must be day or month")))?,
+        None => Period::Day
+    };
+    let months_back = months_back.unwrap_or(0);
+    let months = months.unwrap_or(1);
+    if months == 0 || months > MAX_MONTHS {
+        return Err(ApiError::BadInput(format!("months must be between 1 and {}", MAX_MONTHS)));
+    }
+    if months_back >= MAX_MONTHS {
+        return Err(ApiError::BadInput(format!("months_back must be less than {}", MAX_MONTHS)));
+    }
+    let now = Utc::now();
+    let out_of_range = || ApiError::BadInput(String::from("months_back and months are out of range"));
+    let (from, _) = get_start_and_end_for_previous_month(now, months_back + months - 1).ok_or_else(out_of_range)?;
+    let (_, to) = get_start_and_end_for_previous_month(now, months_back).ok_or_else(out_of_range)?;
+    Ok(Json(youtube_manager.get_spend(from, to, period).await?))
+}
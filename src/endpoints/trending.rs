@@ -0,0 +1,14 @@
+use rocket::State;
+use crate::youtube_manager::{YoutubeManager, DEFAULT_REGION};
+use rocket::serde::json::Json;
+use crate::models::page::Page;
+use crate::models::video::Video;
+use crate::error::ApiError;
+use crate::request_context::RequestContext;
+use crate::clients::Scope;
+
+#[get("/v1/trending?<region>&<category>&<page_token>")]
+pub async fn trending(youtube_manager: &State<YoutubeManager>, region: Option<String>, category: Option<String>, page_token: Option<String>, ctx: RequestContext) -> Result<Json<Page<Video>>, ApiError> {
+    ctx.require(Scope::Read)?;
+    Ok(Json(youtube_manager.trending(&ctx, region.unwrap_or(DEFAULT_REGION.to_string()), category, page_token).await?))
+}
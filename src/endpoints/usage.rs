@@ -0,0 +1,27 @@
+use rocket::State;
+use rocket::serde::json::Json;
+use chrono::Utc;
+use crate::youtube_manager::YoutubeManager;
+use crate::models::usage::UsageReport;
+use crate::usage::MAX_MONTHS;
+use crate::date_util::get_start_and_end_for_previous_month;
+use crate::error::ApiError;
+use crate::AdminKey;
+
+/// One report per calendar month, from the current month back `months_back` months, newest first
+#[get("/v1/admin/usage?<months_back>")]
+pub fn usage(youtube_manager: &State<YoutubeManager>, months_back: Option<u32>, _admin_key: AdminKey) -> Result<Json<Vec<UsageReport>>, ApiError> {
+    let months_back = months_back.unwrap_or(0);
+    if months_back as usize >= MAX_MONTHS {
+        return Err(ApiError::BadInput(format!("months_back must be less than {}", MAX_MONTHS)));
+    }
+    let now = Utc::now();
+    let reports = (0..=months_back)
+        .map(|count| {
+            let (start, _) = get_start_and_end_for_previous_month(now, count)
+                .ok_or(ApiError::BadInput(String::from("months_back is out of range")))?;
+            Ok(youtube_manager.get_usage().get_report(start))
+        })
+        .collect::<Result<Vec<UsageReport>, ApiError>>()?;
+    Ok(Json(reports))
+}
@@ -1,41 +1,91 @@
 use crate::youtube_manager::YoutubeManager;
-use crate::ApiKey;
-use rocket_contrib::json::Json;
+use crate::request_context::RequestContext;
+use crate::clients::Scope;
+use rocket::serde::json::Json;
 use crate::models::video::Video;
+use crate::models::activity::Activity;
+use crate::error::ApiError;
 use rocket::State;
-use anyhow::Result;
-use rocket::http::Status;
+use chrono::DateTime;
+use crate::budget::Partial;
+use crate::logger;
+
+fn check_date(name: &str, value: &Option<String>) -> Result<(), ApiError> {
+    if let Some(value) = value {
+        if DateTime::parse_from_rfc3339(value).is_err() {
+            return Err(ApiError::BadInput(format!("{} must be an RFC 3339 date", name)));
+        }
+    }
+    Ok(())
+}
 
 #[get("/v1/channel/<id>/most_recent")]
-pub fn get_most_recent_videos_for_channel(youtube_manager: State<YoutubeManager>, id: String, _api_key: ApiKey) -> Result<Json<Vec<Video>>> {
-    youtube_manager.list_latest_videos_for_channel(id)
-        .map(|list| Json(list))
+pub async fn get_most_recent_videos_for_channel(youtube_manager: &State<YoutubeManager>, id: String, ctx: RequestContext) -> Result<Json<Vec<Video>>, ApiError> {
+    ctx.require(Scope::Search)?;
+    Ok(Json(youtube_manager.list_latest_videos_for_channel(&ctx, id).await?))
+}
+
+#[get("/v1/channel/<id>/live")]
+pub async fn get_live_videos_for_channel(youtube_manager: &State<YoutubeManager>, id: String, ctx: RequestContext) -> Result<Json<Vec<Video>>, ApiError> {
+    ctx.require(Scope::Search)?;
+    Ok(Json(youtube_manager.list_live_videos_for_channel(&ctx, id).await?))
+}
+
+#[get("/v1/channel/<id>/upcoming")]
+pub async fn get_upcoming_videos_for_channel(youtube_manager: &State<YoutubeManager>, id: String, ctx: RequestContext) -> Result<Json<Vec<Video>>, ApiError> {
+    ctx.require(Scope::Search)?;
+    Ok(Json(youtube_manager.list_upcoming_videos_for_channel(&ctx, id).await?))
+}
+
+#[get("/v1/channel/<id>/activities?<published_after>&<published_before>")]
+pub async fn get_activities_for_channel(youtube_manager: &State<YoutubeManager>, id: String, published_after: Option<String>, published_before: Option<String>, ctx: RequestContext) -> Result<Json<Vec<Activity>>, ApiError> {
+    ctx.require(Scope::Read)?;
+    check_date("published_after", &published_after)?;
+    check_date("published_before", &published_before)?;
+    Ok(Json(youtube_manager.list_activities_for_channel(&ctx, id, published_after, published_before).await?))
 }
 
-#[get("/v1/channel/<id>/videos")]
-pub fn get_videos_for_channel(youtube_manager: State<YoutubeManager>, id: String, _api_key: ApiKey) -> Result<Json<Vec<Video>>> {
-    let channel_result = youtube_manager.single_channel(id)?;
+#[get("/v1/channel/<id>/videos?<cursor>")]
+pub async fn get_videos_for_channel(youtube_manager: &State<YoutubeManager>, id: String, cursor: Option<String>, ctx: RequestContext) -> Result<Partial<Json<Vec<Video>>>, ApiError> {
+    ctx.require(Scope::Read)?;
+    let channel_result = youtube_manager.single_channel(&ctx, id).await?;
 
-    match channel_result {
+    match channel_result.and_then(|channel| channel.get_all_videos_playlist_id()) {
         None => {
-            Err(anyhow::Error::msg(Status::NotFound.reason))
+            Err(ApiError::NotFound(String::from("Channel not found")))
         },
-        Some(channel) => {
-            let playlist_id = channel.get_all_videos_playlist_id().unwrap();
-            get_videos_for_playlist(youtube_manager, playlist_id, _api_key)
+        Some(playlist_id) => {
+            get_videos_for_playlist(youtube_manager, playlist_id, cursor, ctx).await
         }
     }
 }
 
-#[get("/v1/playlist/<id>/videos")]
-pub fn get_videos_for_playlist(youtube_manager: State<YoutubeManager>, id: String, _api_key: ApiKey) -> Result<Json<Vec<Video>>> {
-    let mut page_token: Option<String> = None;
+/// Fetches every page, unless the request's budget runs out after at least one page
+/// in which case the videos so far are returned with the cursor of the next page
+#[get("/v1/playlist/<id>/videos?<cursor>")]
+pub async fn get_videos_for_playlist(youtube_manager: &State<YoutubeManager>, id: String, cursor: Option<String>, ctx: RequestContext) -> Result<Partial<Json<Vec<Video>>>, ApiError> {
+    ctx.require(Scope::Read)?;
+    let mut page_token: Option<String> = cursor;
     let mut results: Vec<Video> = vec![];
+    let mut pages = 0;
 
     loop {
-        let (videos, next_page_token) = youtube_manager.list_videos_for_playlist(id.clone(), page_token.clone())?;
+        let (videos, next_page_token) = match youtube_manager.list_videos_for_playlist(&ctx, id.clone(), page_token.clone()).await {
+            Ok(page) => page,
+            Err(error) => {
+                let error = ApiError::from(error);
+                if let ApiError::BudgetExhausted(_) = error {
+                    if pages > 0 {
+                        logger::info(Some(ctx.get_request_id()), "Returning partial result", &[("reason", error.get_message().to_string()), ("pages", pages.to_string())]);
+                        return Ok(Partial::new(Json(results), page_token));
+                    }
+                }
+                return Err(error);
+            }
+        };
+        pages += 1;
         for video in videos {
-            &results.push(video);
+            results.push(video);
         }
         page_token = next_page_token;
         if page_token.is_none() {
@@ -43,5 +93,5 @@ pub fn get_videos_for_playlist(youtube_manager: State<YoutubeManager>, id: Strin
         }
     }
 
-    Ok(Json(results))
+    Ok(Partial::new(Json(results), None))
 }
@@ -0,0 +1,189 @@
+use std::fmt::{Display, Formatter};
+use rocket::{Request, Response};
+use rocket::http::{Status, Header};
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use serde::Serialize;
+use crate::request_id::request_id;
+use crate::logger::{self, Level};
+
+#[derive(Debug, Clone)]
+pub enum ApiError {
+    NotFound(String),
+    BadInput(String),
+    Unauthorized(String),
+    Forbidden(String),
+    QuotaExhausted(String),
+    /// Message and seconds until the client can retry
+    RateLimited(String, u64),
+    /// Message and seconds until the client's budget is reset
+    ClientQuotaExhausted(String, u64),
+    /// The time or quota the caller allowed for the request ran out
+    BudgetExhausted(String),
+    UpstreamClient(String),
+    UpstreamServer(String),
+    Timeout(String),
+    Internal(String),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+    request_id: String,
+}
+
+impl ApiError {
+    pub fn get_code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound(_) => "not_found",
+            ApiError::BadInput(_) => "bad_input",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::QuotaExhausted(_) => "quota_exhausted",
+            ApiError::RateLimited(_, _) => "rate_limited",
+            ApiError::ClientQuotaExhausted(_, _) => "client_quota_exhausted",
+            ApiError::BudgetExhausted(_) => "budget_exhausted",
+            ApiError::UpstreamClient(_) => "upstream_client_error",
+            ApiError::UpstreamServer(_) => "upstream_server_error",
+            ApiError::Timeout(_) => "timeout",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn get_status(&self) -> Status {
+        match self {
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::BadInput(_) => Status::BadRequest,
+            ApiError::Unauthorized(_) => Status::Unauthorized,
+            ApiError::Forbidden(_) => Status::Forbidden,
+            ApiError::QuotaExhausted(_) => Status::ServiceUnavailable,
+            ApiError::RateLimited(_, _) => Status::TooManyRequests,
+            ApiError::ClientQuotaExhausted(_, _) => Status::TooManyRequests,
+            ApiError::BudgetExhausted(_) => Status::TooManyRequests,
+            ApiError::UpstreamClient(_) => Status::BadRequest,
+            ApiError::UpstreamServer(_) => Status::BadGateway,
+            ApiError::Timeout(_) => Status::GatewayTimeout,
+            ApiError::Internal(_) => Status::InternalServerError,
+        }
+    }
+
+    pub fn get_retry_after(&self) -> Option<u64> {
+        match self {
+            ApiError::RateLimited(_, seconds) |
+            ApiError::ClientQuotaExhausted(_, seconds) => Some(*seconds),
+            _ => None
+        }
+    }
+
+    pub fn get_message(&self) -> &str {
+        match self {
+            ApiError::NotFound(message) |
+            ApiError::BadInput(message) |
+            ApiError::Unauthorized(message) |
+            ApiError::Forbidden(message) |
+            ApiError::QuotaExhausted(message) |
+            ApiError::RateLimited(message, _) |
+            ApiError::ClientQuotaExhausted(message, _) |
+            ApiError::BudgetExhausted(message) |
+            ApiError::UpstreamClient(message) |
+            ApiError::UpstreamServer(message) |
+            ApiError::Timeout(message) |
+            ApiError::Internal(message) => message
+        }
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.get_code(), self.get_message())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<ApiError>() {
+            Ok(api_error) => return api_error,
+            Err(error) => error
+        };
+        if let Some(reqwest_error) = error.downcast_ref::<reqwest::Error>() {
+            if reqwest_error.is_timeout() {
+                return ApiError::Timeout(String::from("YouTube did not respond in time"));
+            }
+            return ApiError::UpstreamServer(String::from("Unable to reach YouTube"));
+        }
+        logger::error(None, "Unexpected error", &[("error", format!("{:?}", error))]);
+        ApiError::Internal(String::from("Server Error"))
+    }
+}
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let request_id = request_id(request);
+        let body = ErrorBody {
+            code: self.get_code(),
+            message: self.get_message().to_string(),
+            request_id: request_id.clone(),
+        };
+        let level = if self.get_status().code >= 500 { Level::Error } else { Level::Info };
+        logger::log(level, Some(&request_id), "Error response", &[
+            ("code", self.get_code().to_string()),
+            ("message", self.get_message().to_string())]);
+        let mut response = Response::build_from(Json(body).respond_to(request)?);
+        response.status(self.get_status())
+            .header(Header::new("X-Request-Id", request_id));
+        if let Some(seconds) = self.get_retry_after() {
+            response.header(Header::new("Retry-After", seconds.to_string()));
+        }
+        response.ok()
+    }
+}
+
+/// Request guards can only fail with a status, so they store the full error here for the catchers
+struct GuardError(Option<ApiError>);
+
+pub fn set_guard_error(request: &Request, error: ApiError) {
+    request.local_cache(|| GuardError(Some(error)));
+}
+
+fn get_guard_error(request: &Request) -> Option<ApiError> {
+    request.local_cache(|| GuardError(None)).0.clone()
+}
+
+#[catch(400)]
+pub fn bad_request(request: &Request) -> ApiError {
+    get_guard_error(request).unwrap_or(ApiError::BadInput(String::from("Bad Request")))
+}
+
+#[catch(401)]
+pub fn unauthorized(request: &Request) -> ApiError {
+    get_guard_error(request).unwrap_or(ApiError::Unauthorized(String::from("Missing or invalid x-api-key or token")))
+}
+
+#[catch(403)]
+pub fn forbidden(request: &Request) -> ApiError {
+    get_guard_error(request).unwrap_or(ApiError::Forbidden(String::from("Key doesn't have access to this endpoint")))
+}
+
+#[catch(404)]
+pub fn not_found(_request: &Request) -> ApiError {
+    ApiError::NotFound(String::from("Not Found"))
+}
+
+#[catch(422)]
+pub fn unprocessable(_request: &Request) -> ApiError {
+    ApiError::BadInput(String::from("Invalid or missing parameters"))
+}
+
+#[catch(429)]
+pub fn too_many_requests(request: &Request) -> ApiError {
+    get_guard_error(request).unwrap_or(ApiError::RateLimited(String::from("Too many requests"), 60))
+}
+
+#[catch(500)]
+pub fn internal_error(_request: &Request) -> ApiError {
+    ApiError::Internal(String::from("Server Error"))
+}
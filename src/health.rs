@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::models::health::{HealthCheck, HealthStatus};
+
+const WINDOW: u64 = 5 * 60;
+const MAX_SAMPLES: usize = 200;
+/// Error rates aren't judged until there are at least this many calls in the window
+const MIN_SAMPLES: usize = 10;
+const DEGRADED_ERROR_RATE: f64 = 0.2;
+const UNHEALTHY_ERROR_RATE: f64 = 0.5;
+
+/// Recent calls to YouTube, only server errors, timeouts and connection failures count as errors
+pub struct UpstreamHealth {
+    samples: Mutex<VecDeque<(Instant, bool)>>,
+}
+
+impl UpstreamHealth {
+    pub fn new() -> UpstreamHealth {
+        UpstreamHealth {
+            samples: Mutex::new(VecDeque::with_capacity(MAX_SAMPLES)),
+        }
+    }
+}
+
+impl UpstreamHealth {
+    pub fn record(&self, failed: bool) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() >= MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back((Instant::now(), failed));
+    }
+
+    pub fn check(&self) -> HealthCheck {
+        let mut samples = self.samples.lock().unwrap();
+        while samples.front().is_some_and(|(time, _)| time.elapsed() > Duration::from_secs(WINDOW)) {
+            samples.pop_front();
+        }
+        if samples.len() < MIN_SAMPLES {
+            return HealthCheck::new(HealthStatus::Ok, format!("{} calls in the last {} seconds", samples.len(), WINDOW));
+        }
+        let failures = samples.iter().filter(|(_, failed)| *failed).count();
+        let rate = failures as f64 / samples.len() as f64;
+        let status = if rate >= UNHEALTHY_ERROR_RATE {
+            HealthStatus::Unhealthy
+        } else if rate >= DEGRADED_ERROR_RATE {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Ok
+        };
+        HealthCheck::new(status, format!("{} of {} calls failed in the last {} seconds", failures, samples.len(), WINDOW))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::health::HealthReport;
+    use std::collections::BTreeMap;
+
+    fn status(health: &UpstreamHealth) -> HealthStatus {
+        let mut checks = BTreeMap::new();
+        checks.insert("upstream", health.check());
+        HealthReport::new(checks).get_status()
+    }
+
+    #[test]
+    fn test_error_rate_thresholds() {
+        //GIVEN upstream health with a few failures
+        let health = UpstreamHealth::new();
+        (0..5).for_each(|_| health.record(true));
+        let few_samples = status(&health);
+        //WHEN more calls are made
+        (0..5).for_each(|_| health.record(false));
+        let half_failed = status(&health);
+        (0..10).for_each(|_| health.record(false));
+        let quarter_failed = status(&health);
+        (0..30).for_each(|_| health.record(false));
+        let tenth_failed = status(&health);
+        //THEN status follows the error rate once there are enough samples
+        assert_eq!(few_samples, HealthStatus::Ok);
+        assert_eq!(half_failed, HealthStatus::Unhealthy);
+        assert_eq!(quarter_failed, HealthStatus::Degraded);
+        assert_eq!(tenth_failed, HealthStatus::Ok);
+    }
+}
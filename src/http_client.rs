@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::{Certificate, Client, Proxy, RequestBuilder, Url};
+use crate::config::{Config, parse_proxy};
+
+/// Client for calls to YouTube, with the configured timeouts, pool, TLS and proxy settings
+pub struct HttpClient {
+    client: Client,
+}
+
+impl HttpClient {
+    pub fn new(config: &Config) -> Result<HttpClient> {
+        let timeouts = &config.timeouts;
+        let mut builder = Client::builder()
+            .connect_timeout(Duration::from_secs(timeouts.connect))
+            .read_timeout(Duration::from_secs(timeouts.read))
+            .timeout(Duration::from_secs(timeouts.total))
+            .pool_max_idle_per_host(config.youtube.pool_size)
+            .pool_idle_timeout(Duration::from_secs(timeouts.pool_idle));
+
+        if config.youtube.http2 {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(path) = &config.youtube.ca_bundle {
+            for certificate in load_certificates(path)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if let Some(proxy) = make_proxy(config)? {
+            builder = builder.proxy(proxy);
+        }
+
+        Ok(HttpClient {
+            client: builder.build().context("Unable to create HTTP client")?,
+        })
+    }
+}
+
+impl HttpClient {
+    pub fn get(&self, url: Url) -> RequestBuilder {
+        self.client.get(url)
+    }
+}
+
+fn make_proxy(config: &Config) -> Result<Option<Proxy>> {
+    let url = match &config.youtube.proxy {
+        Some(proxy) => parse_proxy(proxy).ok_or(anyhow!("Invalid proxy"))?,
+        None => return Ok(None)
+    };
+    let mut proxy = if config.youtube.no_proxy.is_empty() {
+        Proxy::all(url).context("Invalid proxy")?
+    } else {
+        let no_proxy = config.youtube.no_proxy.clone();
+        Proxy::custom(move |target| {
+            if is_no_proxy(target.host_str().unwrap_or_default(), &no_proxy) {
+                None
+            } else {
+                Some(url.clone())
+            }
+        })
+    };
+    if let Some(username) = &config.youtube.proxy_username {
+        proxy = proxy.basic_auth(username, config.youtube.proxy_password.as_deref().unwrap_or_default());
+    }
+    Ok(Some(proxy))
+}
+
+/// `*` matches every host, other entries match the host and its subdomains
+fn is_no_proxy(host: &str, no_proxy: &[String]) -> bool {
+    let host = host.to_lowercase();
+    no_proxy.iter()
+        .map(|entry| entry.trim().trim_start_matches('.').to_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| entry == "*" || host == entry || host.ends_with(&format!(".{}", entry)))
+}
+
+fn load_certificates(path: &PathBuf) -> Result<Vec<Certificate>> {
+    let pem = fs::read(path).with_context(|| format!("Unable to read CA bundle {}", path.display()))?;
+    let certificates = Certificate::from_pem_bundle(&pem).with_context(|| format!("Invalid CA bundle {}", path.display()))?;
+    if certificates.is_empty() {
+        bail!("No certificates in CA bundle {}", path.display());
+    }
+    Ok(certificates)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_no_proxy_matching() {
+        //GIVEN a no proxy list
+        let no_proxy = vec![String::from("localhost"), String::from(".internal.example.com"), String::from(" 127.0.0.1 ")];
+        //WHEN matching hosts
+        //THEN hosts and subdomains of entries skip the proxy
+        assert!(is_no_proxy("localhost", &no_proxy));
+        assert!(is_no_proxy("api.internal.example.com", &no_proxy));
+        assert!(is_no_proxy("internal.example.com", &no_proxy));
+        assert!(is_no_proxy("127.0.0.1", &no_proxy));
+        assert!(!is_no_proxy("www.googleapis.com", &no_proxy));
+        assert!(!is_no_proxy("notlocalhost", &no_proxy));
+        assert!(is_no_proxy("www.googleapis.com", &[String::from("*")]));
+    }
+
+    #[test]
+    fn test_client_is_built_with_proxy_settings() {
+        //GIVEN config with an authenticated proxy and a no proxy list
+        let mut config = Config::default();
+        config.youtube.proxy = Some(String::from("proxy.example.com:3128"));
+        config.youtube.proxy_username = Some(String::from("user"));
+        config.youtube.proxy_password = Some(String::from("secret"));
+        config.youtube.no_proxy = vec![String::from("localhost")];
+        config.youtube.http2 = true;
+        //WHEN the client is built
+        let client = HttpClient::new(&config);
+        //THEN it's valid
+        assert!(client.is_ok());
+    }
+}
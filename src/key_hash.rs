@@ -0,0 +1,67 @@
+use rand::Rng;
+use sha2::{Sha256, Digest};
+use subtle::ConstantTimeEq;
+
+const SCHEME: &str = "sha256";
+const SALT_LENGTH: usize = 16;
+
+/// Salted SHA-256 of client keys, the pepper comes from the environment so a leaked clients file isn't enough to brute force them
+pub struct KeyHasher {
+    pepper: String,
+}
+
+impl KeyHasher {
+    pub fn new(pepper: String) -> KeyHasher {
+        KeyHasher { pepper }
+    }
+}
+
+impl KeyHasher {
+    /// Returns `sha256$<salt>$<digest>`, both hex encoded
+    pub fn hash(&self, key: &str) -> String {
+        let salt: [u8; SALT_LENGTH] = rand::thread_rng().gen();
+        format!("{}${}${}", SCHEME, hex::encode(salt), hex::encode(self.digest(&salt, key)))
+    }
+
+    /// Invalid hashes never match
+    pub fn verify(&self, key: &str, hash: &str) -> bool {
+        let parts: Vec<&str> = hash.split('$').collect();
+        if parts.len() != 3 || parts[0] != SCHEME {
+            return false;
+        }
+        match (hex::decode(parts[1]), hex::decode(parts[2])) {
+            (Ok(salt), Ok(expected)) => bool::from(self.digest(&salt, key).ct_eq(&expected)),
+            _ => false
+        }
+    }
+
+    fn digest(&self, salt: &[u8], key: &str) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(salt);
+        hasher.update(key.as_bytes());
+        hasher.update(self.pepper.as_bytes());
+        hasher.finalize().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hash_and_verify() {
+        //GIVEN hasher with a pepper
+        let hasher = KeyHasher::new(String::from("pepper"));
+        //WHEN the same key is hashed twice
+        let first = hasher.hash("secret");
+        let second = hasher.hash("secret");
+        //THEN hashes are salted differently and both verify
+        assert_ne!(first, second);
+        assert!(first.starts_with("sha256$"));
+        assert!(hasher.verify("secret", &first));
+        assert!(hasher.verify("secret", &second));
+        assert!(!hasher.verify("secret2", &first));
+        assert!(!KeyHasher::new(String::from("other")).verify("secret", &first));
+        assert!(!hasher.verify("secret", "secret"));
+    }
+}
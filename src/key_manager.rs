@@ -1,57 +1,163 @@
 use std::collections::HashMap;
+use crate::models::key_status::KeyStatus;
+use crate::retry::mask_key;
+use crate::logger;
+use crate::key_store::KeyEntry;
+use crate::models::reconciliation::{Reconciliation, KeyReconciliation};
 
 const DEFAULT_QUOTA: usize = 10000;
 
+const REASONS_EXHAUSTED: [&str; 2] = ["quotaExceeded", "dailyLimitExceeded"];
+/// Per second or minute limits, these clear on their own long before the daily reset
+const REASONS_RATE_LIMITED: [&str; 2] = ["rateLimitExceeded", "userRateLimitExceeded"];
+const REASONS_DISABLE: [&str; 5] = ["keyInvalid", "keyExpired", "accessNotConfigured", "ipRefererBlocked", "API_KEY_INVALID"];
+
+/// What to do with a key after YouTube rejected a request made with it
+#[derive(Debug, PartialEq)]
+pub enum KeyAction {
+    /// Key is out of quota until the next reset, retry with another key
+    Exhausted,
+    /// Key is briefly over a rate limit, retry after a backoff without taking it out of the pool
+    RateLimited,
+    /// Key will never work again, retry with another key
+    Disable,
+    /// Not a problem with the key, return the error to the client
+    PassThrough,
+}
+
+impl KeyAction {
+    pub fn from_response(status: u16, reasons: &[String]) -> KeyAction {
+        if reasons.iter().any(|reason| REASONS_DISABLE.contains(&reason.as_str())) {
+            KeyAction::Disable
+        } else if reasons.iter().any(|reason| REASONS_EXHAUSTED.contains(&reason.as_str())) {
+            KeyAction::Exhausted
+        } else if status == 429 || reasons.iter().any(|reason| REASONS_RATE_LIMITED.contains(&reason.as_str())) {
+            KeyAction::RateLimited
+        } else {
+            KeyAction::PassThrough
+        }
+    }
+}
+
+/// Number of times in a row a key can run out of quota before being used successfully before it's disabled
+const FULL_QUOTA_STRIKES: usize = 3;
+const MAX_EXHAUSTION_SAMPLES: usize = 30;
+
+struct KeyState {
+    key: String,
+    label: String,
+    spent: usize,
+    exhausted: bool,
+    exhaustion_samples: Vec<usize>,
+    disabled_reason: Option<String>,
+    full_quota_strikes: usize,
+    used_since_reset: bool,
+}
+
 pub struct KeyManager {
-    key_quotas: HashMap<String, usize>,
+    keys: Vec<KeyState>,
     last_used: usize,
+    reconciliation: bool,
+}
+
+impl KeyState {
+    fn new(entry: KeyEntry) -> KeyState {
+        KeyState {
+            key: entry.key,
+            label: entry.label,
+            spent: 0,
+            exhausted: false,
+            exhaustion_samples: vec![],
+            disabled_reason: entry.disabled_reason,
+            full_quota_strikes: 0,
+            used_since_reset: false,
+        }
+    }
 }
 
 impl KeyManager {
     pub fn new(keys: Vec<String>) -> KeyManager {
-        let mut map = HashMap::with_capacity(keys.len());
-        for key in keys {
-            map.insert(key, DEFAULT_QUOTA);
-        }
+        let entries = keys.into_iter()
+            .enumerate()
+            .map(|(i, key)| KeyEntry { key, label: format!("key{}", i), disabled_reason: None })
+            .collect();
+        KeyManager::from_entries(entries)
+    }
 
-        return KeyManager {
-            key_quotas: map,
+    pub fn from_entries(entries: Vec<KeyEntry>) -> KeyManager {
+        KeyManager {
+            keys: entries.into_iter().map(KeyState::new).collect(),
             last_used: 0,
-        };
+            reconciliation: false,
+        }
+    }
+}
+
+impl KeyState {
+    fn get_remaining(&self) -> usize {
+        if self.exhausted {
+            0
+        } else {
+            DEFAULT_QUOTA.saturating_sub(self.spent)
+        }
     }
 }
 
 impl KeyManager {
     fn set_last_used(&mut self, idx: usize) {
         self.last_used = idx + 1;
-        if self.last_used >= self.key_quotas.len() {
+        if self.last_used >= self.keys.len() {
             self.last_used = 0;
         }
     }
 
+    fn find(&mut self, key: &str) -> Option<&mut KeyState> {
+        self.keys.iter_mut().find(|state| state.key == key)
+    }
+
+    /// In reconciliation mode keys are used until YouTube reports them as exhausted rather than until
+    /// the estimated spend reaches the quota, so the estimate can be compared with the real limit
+    pub fn set_reconciliation_mode(&mut self, enabled: bool) {
+        self.reconciliation = enabled;
+    }
+
+    /// Restores the quota of every key, disabled keys stay disabled
     pub fn reset_keys(&mut self) {
-        self.key_quotas.iter_mut()
-            .for_each(|(_, v)| *v = DEFAULT_QUOTA);
+        self.keys.iter_mut()
+            .for_each(|state| {
+                state.spent = 0;
+                state.exhausted = false;
+                state.used_since_reset = false;
+            });
+    }
+
+    /// Number of keys that could currently pay for a request costing `cost`
+    pub fn count_available(&self, cost: usize) -> usize {
+        self.keys.iter()
+            .filter(|state| !state.exhausted && state.disabled_reason.is_none())
+            .filter(|state| self.reconciliation || state.spent + cost <= DEFAULT_QUOTA)
+            .count()
     }
 
     pub fn get_key(&mut self, cost: usize) -> Option<String> {
-        let keys: Vec<String> = self.key_quotas.iter()
-            .map(|(key, _)| key)
-            .cloned()
-            .collect();
+        if self.keys.is_empty() {
+            return None;
+        }
 
+        let reconciliation = self.reconciliation;
         let last_key_used = self.last_used;
         let mut i = self.last_used;
         loop {
-            let quota = self.key_quotas[&keys[i]];
-            if quota >= cost {
-                let key = keys[i].clone();
-                self.key_quotas.insert(key.clone(), quota - cost);
+            let state = &mut self.keys[i];
+            let affordable = reconciliation || state.spent + cost <= DEFAULT_QUOTA;
+            if affordable && !state.exhausted && state.disabled_reason.is_none() {
+                state.spent += cost;
+                let key = state.key.clone();
                 self.set_last_used(i);
                 return Some(key);
             } else {
                 i += 1;
-                if i >= self.key_quotas.len() {
+                if i >= self.keys.len() {
                     i = 0;
                 }
                 if i == last_key_used {
@@ -61,22 +167,159 @@ impl KeyManager {
         }
     }
 
-    pub fn set_key_as_expired(&mut self, key: &str) {
-        if self.key_quotas[key] == DEFAULT_QUOTA {
-            eprintln!("Key {} has probably permanently expired", key);
+    pub fn mark_success(&mut self, key: &str) {
+        if let Some(state) = self.find(key) {
+            state.used_since_reset = true;
+            state.full_quota_strikes = 0;
+        }
+    }
+
+    /// Keys that keep running out of quota without ever being used successfully are assumed to be revoked and are disabled
+    /// Returns true if the key was disabled by this call
+    pub fn set_key_as_expired(&mut self, key: &str) -> bool {
+        let mut disabled = false;
+        if let Some(state) = self.find(key) {
+            if !state.used_since_reset {
+                state.full_quota_strikes += 1;
+                if state.full_quota_strikes >= FULL_QUOTA_STRIKES {
+                    let reason = format!("Quota exceeded before first use {} times in a row", state.full_quota_strikes);
+                    logger::warn(None, "Key disabled", &[("key", mask_key(key)), ("reason", reason.clone())]);
+                    state.disabled_reason = Some(reason);
+                    disabled = true;
+                }
+            }
+            if state.exhaustion_samples.len() >= MAX_EXHAUSTION_SAMPLES {
+                state.exhaustion_samples.remove(0);
+            }
+            state.exhaustion_samples.push(state.spent);
+            state.exhausted = true;
+        }
+        disabled
+    }
+
+    pub fn disable_key(&mut self, key: &str, reason: &str) {
+        if let Some(state) = self.find(key) {
+            state.disabled_reason = Some(reason.to_string());
+        }
+    }
+
+    /// Returns false if the key is already in the pool
+    pub fn add_key(&mut self, key: String, label: String) -> bool {
+        if self.keys.iter().any(|state| state.key == key) {
+            return false;
+        }
+        self.keys.push(KeyState::new(KeyEntry { key, label, disabled_reason: None }));
+        true
+    }
+
+    /// Returns false if there is no key at `idx`
+    pub fn remove_key(&mut self, idx: usize) -> bool {
+        if idx >= self.keys.len() {
+            return false;
+        }
+        self.keys.remove(idx);
+        if self.last_used >= self.keys.len() {
+            self.last_used = 0;
+        }
+        true
+    }
+
+    /// Returns false if there is no key at `idx`
+    pub fn set_label(&mut self, idx: usize, label: String) -> bool {
+        match self.keys.get_mut(idx) {
+            Some(state) => {
+                state.label = label;
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Returns false if there is no key at `idx`
+    pub fn disable_key_at(&mut self, idx: usize, reason: &str) -> bool {
+        match self.keys.get_mut(idx) {
+            Some(state) => {
+                state.disabled_reason = Some(reason.to_string());
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Returns false if there is no key at `idx`
+    pub fn enable_key(&mut self, idx: usize) -> bool {
+        match self.keys.get_mut(idx) {
+            Some(state) => {
+                state.disabled_reason = None;
+                state.full_quota_strikes = 0;
+                true
+            }
+            None => false
         }
-        self.key_quotas.insert(key.to_string(), 0);
     }
 
-    pub fn get_status(&self) -> HashMap<usize, usize> {
-        self.key_quotas
+    /// Replaces the pool with `entries`, keys that were already in the pool keep their remaining quota
+    #[cfg_attr(not(unix), allow(dead_code))]
+    pub fn replace_keys(&mut self, entries: Vec<KeyEntry>) {
+        let mut previous: HashMap<String, KeyState> = self.keys.drain(..)
+            .map(|state| (state.key.clone(), state))
+            .collect();
+        self.keys = entries.into_iter()
+            .map(|entry| {
+                let mut state = KeyState::new(entry);
+                if let Some(old) = previous.remove(&state.key) {
+                    state.spent = old.spent;
+                    state.exhausted = old.exhausted;
+                    state.exhaustion_samples = old.exhaustion_samples;
+                    state.full_quota_strikes = old.full_quota_strikes;
+                    state.used_since_reset = old.used_since_reset;
+                }
+                state
+            })
+            .collect();
+        self.last_used = 0;
+    }
+
+    pub fn get_entries(&self) -> Vec<KeyEntry> {
+        self.keys.iter()
+            .map(|state| KeyEntry {
+                key: state.key.clone(),
+                label: state.label.clone(),
+                disabled_reason: state.disabled_reason.clone(),
+            })
+            .collect()
+    }
+
+    pub fn get_status(&self) -> HashMap<usize, KeyStatus> {
+        self.keys
             .iter()
             .enumerate()
-            .map(|(i, (_, v))| {
-                (i, *v)
+            .map(|(i, state)| {
+                (i, KeyStatus::new(state.label.clone(), state.get_remaining(), state.disabled_reason.clone()))
             })
             .collect()
     }
+
+    /// Label of `key`, used to identify it in logs and audit records without the key itself
+    pub fn get_label(&self, key: &str) -> Option<String> {
+        self.keys.iter()
+            .find(|state| state.key == key)
+            .map(|state| state.label.clone())
+    }
+
+    /// Label, remaining and spent quota for each key
+    pub fn get_quota_usage(&self) -> Vec<(String, usize, usize)> {
+        self.keys.iter()
+            .map(|state| (state.label.clone(), state.get_remaining(), state.spent))
+            .collect()
+    }
+
+    pub fn get_reconciliation(&self) -> Reconciliation {
+        let keys = self.keys.iter()
+            .map(|state| KeyReconciliation::new(state.label.clone(), DEFAULT_QUOTA, state.spent, &state.exhaustion_samples))
+            .collect();
+        Reconciliation::new(self.reconciliation, keys)
+    }
 }
 
 #[cfg(test)]
@@ -176,6 +419,35 @@ mod tests {
         assert!(fifth.is_none());
     }
 
+    #[test]
+    fn test_disabled_keys_are_skipped() {
+        //GIVEN key manager with multiple keys
+        let mut key_manager = KeyManager::new_test(vec!["key1", "key2"]);
+        let first = key_manager.get_key(100).unwrap();
+        //WHEN the first key is disabled
+        key_manager.disable_key(&first, "keyInvalid");
+        let keys: Vec<String> = (0..4).filter_map(|_| key_manager.get_key(100)).collect();
+        //THEN it is never returned
+        assert_eq!(keys.len(), 4);
+        assert!(keys.iter().all(|key| key != &first));
+    }
+
+    #[test]
+    fn test_key_action_from_response() {
+        let reasons = |list: Vec<&str>| -> Vec<String> { list.iter().map(|s| s.to_string()).collect() };
+        assert_eq!(KeyAction::from_response(403, &reasons(vec!["quotaExceeded"])), KeyAction::Exhausted);
+        assert_eq!(KeyAction::from_response(403, &reasons(vec!["dailyLimitExceeded"])), KeyAction::Exhausted);
+        assert_eq!(KeyAction::from_response(429, &reasons(vec!["quotaExceeded"])), KeyAction::Exhausted);
+        assert_eq!(KeyAction::from_response(429, &reasons(vec![])), KeyAction::RateLimited);
+        assert_eq!(KeyAction::from_response(403, &reasons(vec!["rateLimitExceeded"])), KeyAction::RateLimited);
+        assert_eq!(KeyAction::from_response(403, &reasons(vec!["userRateLimitExceeded"])), KeyAction::RateLimited);
+        assert_eq!(KeyAction::from_response(400, &reasons(vec!["keyInvalid"])), KeyAction::Disable);
+        assert_eq!(KeyAction::from_response(400, &reasons(vec!["badRequest", "API_KEY_INVALID"])), KeyAction::Disable);
+        assert_eq!(KeyAction::from_response(403, &reasons(vec!["accessNotConfigured"])), KeyAction::Disable);
+        assert_eq!(KeyAction::from_response(403, &reasons(vec!["forbidden"])), KeyAction::PassThrough);
+        assert_eq!(KeyAction::from_response(404, &reasons(vec!["videoNotFound"])), KeyAction::PassThrough);
+    }
+
     #[test]
     fn test_status() {
         //GIVEN key manager with keys
@@ -183,7 +455,118 @@ mod tests {
         //WHEN keys are used
         let status = key_manager.get_status();
         //THEN check status output
-        assert!(status.iter().any(|(k, q)| k == &0 && q == &10000));
-        assert!(status.iter().any(|(k, q)| k == &1 && q == &10000));
+        assert!(status.iter().any(|(k, q)| k == &0 && q.get_quota() == 10000));
+        assert!(status.iter().any(|(k, q)| k == &1 && q.get_quota() == 10000));
+    }
+
+    #[test]
+    fn test_disabled_keys_survive_reset() {
+        //GIVEN key manager with a disabled key
+        let mut key_manager = KeyManager::new_test(vec!["key1", "key2"]);
+        key_manager.disable_key("key1", "keyInvalid");
+        //WHEN keys are reset
+        key_manager.reset_keys();
+        //THEN key is still disabled
+        assert!(key_manager.get_status()[&0].is_disabled());
+        assert!(!key_manager.get_status()[&1].is_disabled());
+    }
+
+    #[test]
+    fn test_repeated_full_quota_failures_disable_key() {
+        //GIVEN key manager with one key that fails before being used every day
+        let mut key_manager = KeyManager::new_test(vec!["key1"]);
+        for strike in 1..=FULL_QUOTA_STRIKES {
+            assert!(!key_manager.get_status()[&0].is_disabled());
+            key_manager.get_key(100);
+            assert_eq!(key_manager.set_key_as_expired("key1"), strike == FULL_QUOTA_STRIKES);
+            key_manager.reset_keys();
+        }
+        //WHEN a key is requested
+        let key = key_manager.get_key(100);
+        //THEN key has been disabled
+        assert!(key.is_none());
+        assert!(key_manager.get_status()[&0].is_disabled());
+    }
+
+    #[test]
+    fn test_success_clears_strikes() {
+        //GIVEN key manager with one key that has failed before being used
+        let mut key_manager = KeyManager::new_test(vec!["key1"]);
+        for _ in 0..FULL_QUOTA_STRIKES - 1 {
+            key_manager.set_key_as_expired("key1");
+            key_manager.reset_keys();
+        }
+        //WHEN the key is used successfully and then runs out
+        key_manager.mark_success("key1");
+        key_manager.set_key_as_expired("key1");
+        key_manager.reset_keys();
+        //THEN key is not disabled
+        assert!(!key_manager.get_status()[&0].is_disabled());
+    }
+
+    #[test]
+    fn test_replace_keys_keeps_quota() {
+        //GIVEN key manager with a used key
+        let mut key_manager = KeyManager::new_test(vec!["key1", "key2"]);
+        key_manager.get_key(100);
+        //WHEN the pool is replaced
+        key_manager.replace_keys(vec![
+            KeyEntry { key: String::from("key1"), label: String::from("main"), disabled_reason: None },
+            KeyEntry { key: String::from("key3"), label: String::from("new"), disabled_reason: None },
+        ]);
+        //THEN existing key keeps its quota, removed key is gone and new key is full
+        let status = key_manager.get_status();
+        assert_eq!(status.len(), 2);
+        assert_eq!(status[&0].get_quota(), 9900);
+        assert_eq!(status[&1].get_quota(), 10000);
+    }
+
+    #[test]
+    fn test_reconciliation_mode_uses_keys_past_estimate() {
+        //GIVEN key manager in reconciliation mode with a key that has used its estimated quota
+        let mut key_manager = KeyManager::new_test(vec!["key1"]);
+        key_manager.set_reconciliation_mode(true);
+        key_manager.get_key(10000);
+        //WHEN more keys are requested until youtube reports the key as exhausted
+        let past_estimate = key_manager.get_key(100);
+        key_manager.set_key_as_expired("key1");
+        let after_exhausted = key_manager.get_key(100);
+        //THEN key is used until it's reported as exhausted and the spend is recorded
+        assert!(past_estimate.is_some());
+        assert!(after_exhausted.is_none());
+        let reconciliation = key_manager.get_reconciliation();
+        assert_eq!(reconciliation.get_keys()[0].get_exhaustions(), 1);
+        assert_eq!(reconciliation.get_keys()[0].get_average_spend_at_exhaustion(), Some(10100));
+    }
+
+    #[test]
+    fn test_add_and_remove_keys() {
+        //GIVEN key manager with one key
+        let mut key_manager = KeyManager::new_test(vec!["key1"]);
+        //WHEN keys are added and removed
+        let added = key_manager.add_key(String::from("key2"), String::from("spare"));
+        let duplicate = key_manager.add_key(String::from("key1"), String::from("again"));
+        let removed = key_manager.remove_key(0);
+        let missing = key_manager.remove_key(3);
+        //THEN only the new key remains
+        assert!(added);
+        assert!(!duplicate);
+        assert!(removed);
+        assert!(!missing);
+        assert_eq!(key_manager.get_key(100), Some(String::from("key2")));
+    }
+
+    #[test]
+    fn test_enable_key() {
+        //GIVEN key manager with a disabled key
+        let mut key_manager = KeyManager::new_test(vec!["key1"]);
+        key_manager.disable_key("key1", "keyInvalid");
+        //WHEN the key is enabled
+        let enabled = key_manager.enable_key(0);
+        let missing = key_manager.enable_key(1);
+        //THEN it can be used again
+        assert!(enabled);
+        assert!(!missing);
+        assert!(key_manager.get_key(100).is_some());
     }
 }
\ No newline at end of file
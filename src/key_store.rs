@@ -0,0 +1,145 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::{Result, Context};
+use serde::{Serialize, Deserialize};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Mutex as AsyncMutex;
+use crate::key_manager::KeyManager;
+use crate::logger;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyEntry {
+    pub key: String,
+    pub label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disabled_reason: Option<String>,
+}
+
+/// JSON file holding the key pool, written after every admin change and reread on SIGHUP
+#[derive(Clone)]
+pub struct KeyStore {
+    path: PathBuf,
+}
+
+impl KeyStore {
+    pub fn new(path: PathBuf) -> KeyStore {
+        KeyStore { path }
+    }
+}
+
+impl KeyStore {
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    pub fn load(&self) -> Result<Vec<KeyEntry>> {
+        let json = fs::read_to_string(&self.path).with_context(|| format!("Unable to read {}", self.path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("Invalid key file {}", self.path.display()))
+    }
+
+    #[cfg(unix)]
+    fn load_if_exists(&self) -> Result<Option<Vec<KeyEntry>>> {
+        if !self.exists() {
+            return Ok(None);
+        }
+        self.load().map(Some)
+    }
+
+    /// Writes to a temporary file first so a crash can't leave a half written key file, only the owner can read it
+    pub fn save(&self, entries: &[KeyEntry]) -> Result<()> {
+        let json = serde_json::to_string_pretty(entries)?;
+        let tmp_path = self.path.with_extension("tmp");
+        let _ = fs::remove_file(&tmp_path);
+        create_private(&tmp_path)
+            .and_then(|mut file| file.write_all(json.as_bytes()))
+            .with_context(|| format!("Unable to write {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &self.path).with_context(|| format!("Unable to write {}", self.path.display()))?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    pub fn start_reload_on_hangup(&self, key_manager: Arc<AsyncMutex<KeyManager>>) -> Result<()> {
+        let mut hangups = signal(SignalKind::hangup())?;
+        let store = KeyStore::new(self.path.clone());
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                let reading = store.clone();
+                let loaded = tokio::task::spawn_blocking(move || reading.load_if_exists()).await;
+                match loaded.map_err(anyhow::Error::from).and_then(|result| result) {
+                    Ok(None) => logger::warn(None, "SIGHUP received but the key file doesn't exist", &[("path", store.path.display().to_string())]),
+                    Ok(Some(entries)) => {
+                        logger::info(None, "Reloaded keys", &[("count", entries.len().to_string()), ("path", store.path.display().to_string())]);
+                        key_manager.lock().await.replace_keys(entries);
+                    }
+                    Err(error) => logger::error(None, "Unable to reload keys", &[("error", format!("{:?}", error))])
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// There's no SIGHUP, the key file is only read at startup
+    #[cfg(not(unix))]
+    pub fn start_reload_on_hangup(&self, _key_manager: Arc<AsyncMutex<KeyManager>>) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn create_private(path: &Path) -> std::io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)
+}
+
+/// Permissions are inherited from the directory
+#[cfg(not(unix))]
+fn create_private(path: &Path) -> std::io::Result<File> {
+    File::create(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        //GIVEN store in temp dir
+        let mut path = std::env::temp_dir();
+        path.push(format!("youtube_keys_test_{}.json", std::process::id()));
+        let store = KeyStore::new(path.clone());
+        let entries = vec![
+            KeyEntry { key: String::from("key1"), label: String::from("main"), disabled_reason: None },
+            KeyEntry { key: String::from("key2"), label: String::from("spare"), disabled_reason: Some(String::from("keyInvalid")) },
+        ];
+        //WHEN entries are saved and loaded
+        store.save(&entries).unwrap();
+        let loaded = store.load().unwrap();
+        fs::remove_file(path).unwrap();
+        //THEN they match
+        assert_eq!(loaded, entries);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_saved_file_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        //GIVEN store in temp dir
+        let mut path = std::env::temp_dir();
+        path.push(format!("youtube_keys_mode_test_{}.json", std::process::id()));
+        let store = KeyStore::new(path.clone());
+        //WHEN entries are saved
+        store.save(&[KeyEntry { key: String::from("key1"), label: String::from("main"), disabled_reason: None }]).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        fs::remove_file(path).unwrap();
+        //THEN only the owner can read or write it
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use futures::Stream;
+use serde::Serialize;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use crate::youtube_client::YoutubeClient;
+use crate::request_context::RequestContext;
+use crate::error::ApiError;
+use crate::logger;
+
+const MIN_POLL_INTERVAL: u64 = 1000;
+const EVENT_HEARTBEAT: &str = ":\n\n";
+const EVENT_END: &str = "event: end\ndata: {}\n\n";
+
+type Subscribers = Arc<Mutex<HashMap<String, Vec<Subscriber>>>>;
+
+struct Subscriber {
+    sender: UnboundedSender<String>,
+    /// Detached from the subscribing request, polls are billed to it while it's the longest listening
+    ctx: RequestContext,
+}
+
+/// Shares one upstream poller per live chat between all clients watching it
+/// The poller stops once the chat ends or the last subscriber disconnects
+pub struct ChatRelay {
+    subscribers: Subscribers,
+}
+
+impl ChatRelay {
+    pub fn new() -> ChatRelay {
+        ChatRelay {
+            subscribers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl ChatRelay {
+    pub fn subscribe(&self, client: Arc<YoutubeClient>, ctx: &RequestContext, chat_id: String) -> ChatStream {
+        let (sender, receiver) = unbounded_channel();
+        let subscriber = Subscriber { sender, ctx: ctx.detached() };
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if let Some(list) = subscribers.get_mut(&chat_id) {
+            list.push(subscriber);
+        } else {
+            subscribers.insert(chat_id.clone(), vec![subscriber]);
+            start_poller(self.subscribers.clone(), client, chat_id);
+        }
+        ChatStream::new(receiver)
+    }
+}
+
+fn start_poller(subscribers: Subscribers, client: Arc<YoutubeClient>, chat_id: String) {
+    tokio::spawn(async move {
+        let mut page_token: Option<String> = None;
+        loop {
+            let ctx = match payer(&subscribers, &chat_id) {
+                Some(ctx) => ctx,
+                None => return
+            };
+            let (events, wait, ended) = match client.live_chat_messages(&ctx, chat_id.clone(), page_token.clone()).await {
+                Ok(page) => {
+                    page_token = page.next_page_token;
+                    let events: Vec<String> = page.items.into_iter()
+                        .map(|item| format_event("message", &item.into_chat_message()))
+                        .collect();
+                    (events, page.polling_interval_millis.max(MIN_POLL_INTERVAL), page.offline_at.is_some())
+                }
+                Err(error) if matches!(error.downcast_ref::<ApiError>(), Some(ApiError::ClientQuotaExhausted(_, _))) => {
+                    // the next subscriber takes over paying for the polls
+                    unsubscribe(&subscribers, &chat_id, ctx.get_request_id());
+                    continue;
+                }
+                Err(error) => {
+                    logger::warn(Some(ctx.get_request_id()), "Live chat stopped", &[("chatId", chat_id.clone()), ("error", format!("{:?}", error))]);
+                    (vec![], 0, true)
+                }
+            };
+
+            if !deliver(&subscribers, &chat_id, events, ended) {
+                return;
+            }
+
+            tokio::time::sleep(Duration::from_millis(wait)).await;
+        }
+    });
+}
+
+/// Who the next poll is billed to, the first subscriber still listening, None once there are none
+fn payer(subscribers: &Subscribers, chat_id: &str) -> Option<RequestContext> {
+    let mut subscribers = subscribers.lock().unwrap();
+    let list = subscribers.entry(chat_id.to_string()).or_insert(vec![]);
+    list.retain(|subscriber| !subscriber.sender.is_closed());
+    match list.first() {
+        Some(subscriber) => Some(subscriber.ctx.detached()),
+        None => {
+            subscribers.remove(chat_id);
+            None
+        }
+    }
+}
+
+/// Ends the stream of a subscriber that can't pay for any more polls
+fn unsubscribe(subscribers: &Subscribers, chat_id: &str, request_id: &str) {
+    let mut subscribers = subscribers.lock().unwrap();
+    if let Some(list) = subscribers.get_mut(chat_id) {
+        list.retain(|subscriber| {
+            if subscriber.ctx.get_request_id() != request_id {
+                return true;
+            }
+            let _ = subscriber.sender.send(String::from(EVENT_END));
+            false
+        });
+    }
+}
+
+/// Sends the events to every subscriber still listening, false once the poller should stop
+fn deliver(subscribers: &Subscribers, chat_id: &str, events: Vec<String>, ended: bool) -> bool {
+    let mut subscribers = subscribers.lock().unwrap();
+    let list = subscribers.entry(chat_id.to_string()).or_insert(vec![]);
+    if events.is_empty() {
+        list.retain(|subscriber| subscriber.sender.send(String::from(EVENT_HEARTBEAT)).is_ok());
+    }
+    for event in events {
+        list.retain(|subscriber| subscriber.sender.send(event.clone()).is_ok());
+    }
+    if ended {
+        list.iter().for_each(|subscriber| { let _ = subscriber.sender.send(String::from(EVENT_END)); });
+    }
+    if ended || list.is_empty() {
+        subscribers.remove(chat_id);
+        return false;
+    }
+    true
+}
+
+fn format_event<T: Serialize>(name: &str, data: &T) -> String {
+    format!("event: {}\ndata: {}\n\n", name, serde_json::to_string(data).unwrap())
+}
+
+/// Server-Sent Events body for a single client, ends when the relay drops the sender
+pub struct ChatStream {
+    receiver: UnboundedReceiver<String>,
+}
+
+impl ChatStream {
+    fn new(receiver: UnboundedReceiver<String>) -> ChatStream {
+        ChatStream { receiver }
+    }
+}
+
+impl Stream for ChatStream {
+    type Item = String;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<String>> {
+        self.get_mut().receiver.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::path::PathBuf;
+    use std::time::Instant;
+    use futures::StreamExt;
+    use mockito::{mock, Matcher};
+    use crate::config::Config;
+    use crate::http_client::HttpClient;
+    use crate::key_manager::KeyManager;
+    use crate::usage::UsageLedger;
+
+    fn make_client() -> Arc<YoutubeClient> {
+        let http_client = HttpClient::new(&Config::default()).unwrap();
+        Arc::new(YoutubeClient::new(KeyManager::new_test(vec!["key0"]), mockito::server_url(), http_client, None, None, Arc::new(UsageLedger::new(None).unwrap())))
+    }
+
+    fn load_test_file(file: &'static str) -> String {
+        let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        file_path.push(format!("test/resources/{}", file));
+        std::fs::read_to_string(file_path).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_share_one_poller() {
+        //GIVEN youtube returning two pages of chat, the first asking for a 1.5s wait
+        let _page2 = mock("GET", Matcher::Regex(r"/liveChat/messages\?.*liveChatId=chat1.*pageToken=page2.*".to_string()))
+            .with_body(load_test_file("live_chat_page2.json")).expect(1).create();
+        let _page1 = mock("GET", Matcher::Regex(r"/liveChat/messages\?.*liveChatId=chat1.*".to_string()))
+            .with_body(load_test_file("live_chat_page1.json")).expect(1).create();
+        let relay = ChatRelay::new();
+        let client = make_client();
+        //WHEN two clients subscribe to the same chat
+        let mut first = relay.subscribe(client.clone(), &RequestContext::system(), String::from("chat1"));
+        let mut second = relay.subscribe(client, &RequestContext::system(), String::from("chat1"));
+        let first_event = first.next().await.unwrap();
+        let second_event = second.next().await.unwrap();
+        let polled_at = Instant::now();
+        let next_event = first.next().await.unwrap();
+        //THEN both get each message from a single upstream call per page, polled at youtube's interval
+        assert!(first_event.contains(r#""id":"message1""#));
+        assert_eq!(second_event, first_event);
+        assert!(next_event.contains(r#""id":"message2""#));
+        assert!(polled_at.elapsed() >= Duration::from_millis(1400));
+        _page1.assert();
+        _page2.assert();
+    }
+
+    #[tokio::test]
+    async fn test_poller_stops_when_last_subscriber_drops() {
+        //GIVEN a client subscribed to a chat
+        let _page1 = mock("GET", Matcher::Regex(r"/liveChat/messages\?.*liveChatId=chat2.*".to_string()))
+            .with_body(load_test_file("live_chat_page1.json")).expect(1).create();
+        let relay = ChatRelay::new();
+        let mut stream = relay.subscribe(make_client(), &RequestContext::system(), String::from("chat2"));
+        stream.next().await.unwrap();
+        //WHEN it disconnects and the next poll is due
+        drop(stream);
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+        //THEN youtube isn't called again and the chat is forgotten
+        _page1.assert();
+        assert!(relay.subscribers.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stream_reads_events_until_closed() {
+        //GIVEN stream with two events queued
+        let (sender, receiver) = unbounded_channel();
+        let stream = ChatStream::new(receiver);
+        sender.send(String::from("event: a\n\n")).unwrap();
+        sender.send(String::from(EVENT_END)).unwrap();
+        //WHEN sender is dropped and stream is read
+        drop(sender);
+        let output: Vec<String> = stream.collect().await;
+        //THEN both events are returned
+        assert_eq!(output.concat(), format!("event: a\n\n{}", EVENT_END));
+    }
+}
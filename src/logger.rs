@@ -0,0 +1,207 @@
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::Instant;
+use chrono::{Utc, SecondsFormat};
+use rocket::{Data, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use serde_json::{Map, Value};
+use crate::request_id::request_id;
+
+/// Anything following these is replaced before it's written, up to the first of the stop characters or whitespace
+const SECRET_MARKERS: [(&str, &str); 4] = [
+    ("key=", "&\"',;)"),
+    ("x-api-key: ", "\"',;"),
+    ("authorization: ", "\"',;\n"),
+    ("bearer ", "\"',;"),
+];
+const REDACTED: &str = "REDACTED";
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static JSON: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Debug = 0,
+    Info = 1,
+    Warn = 2,
+    Error = 3,
+}
+
+impl Level {
+    pub fn parse(value: &str) -> Option<Level> {
+        match value.to_lowercase().as_str() {
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warn" => Some(Level::Warn),
+            "error" => Some(Level::Error),
+            _ => None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+/// Sets the minimum level written and whether lines are JSON or logfmt
+pub fn init(level: Level, json: bool) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+    JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn debug(request_id: Option<&str>, message: &str, fields: &[(&str, String)]) {
+    log(Level::Debug, request_id, message, fields);
+}
+
+pub fn info(request_id: Option<&str>, message: &str, fields: &[(&str, String)]) {
+    log(Level::Info, request_id, message, fields);
+}
+
+pub fn warn(request_id: Option<&str>, message: &str, fields: &[(&str, String)]) {
+    log(Level::Warn, request_id, message, fields);
+}
+
+pub fn error(request_id: Option<&str>, message: &str, fields: &[(&str, String)]) {
+    log(Level::Error, request_id, message, fields);
+}
+
+/// `request_id` ties log lines from upstream calls back to the request that caused them
+pub fn log(level: Level, request_id: Option<&str>, message: &str, fields: &[(&str, String)]) {
+    if (level as u8) < LEVEL.load(Ordering::Relaxed) {
+        return;
+    }
+    eprintln!("{}", format_line(level, request_id, message, fields, JSON.load(Ordering::Relaxed)));
+}
+
+fn format_line(level: Level, request_id: Option<&str>, message: &str, fields: &[(&str, String)], json: bool) -> String {
+    let mut pairs: Vec<(&str, String)> = vec![
+        ("time", Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+        ("level", level.name().to_string()),
+        ("msg", redact(message)),
+    ];
+    if let Some(request_id) = request_id {
+        pairs.push(("requestId", request_id.to_string()));
+    }
+    for (name, value) in fields {
+        pairs.push((name, redact(value)));
+    }
+
+    if json {
+        let map: Map<String, Value> = pairs.into_iter()
+            .map(|(name, value)| (name.to_string(), Value::String(value)))
+            .collect();
+        Value::Object(map).to_string()
+    } else {
+        pairs.into_iter()
+            .map(|(name, value)| format!("{}={}", name, logfmt_value(&value)))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+fn logfmt_value(value: &str) -> String {
+    if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+        format!("{:?}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Replaces API keys in query strings and credentials in headers
+pub fn redact(text: &str) -> String {
+    let lower = text.to_ascii_lowercase();
+    let mut result = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let marker = SECRET_MARKERS.iter()
+            .find(|(marker, _)| lower[i..].starts_with(*marker) && !follows_word(&lower, i));
+        match marker {
+            Some((marker, stops)) => {
+                let start = i + marker.len();
+                // the whole authorization value is secret, including the scheme
+                let stop_at_space = *marker != "authorization: ";
+                let end = text[start..].find(|c: char| (stop_at_space && c.is_whitespace()) || stops.contains(c))
+                    .map(|offset| start + offset)
+                    .unwrap_or(text.len());
+                result.push_str(&text[i..start]);
+                if end > start {
+                    result.push_str(REDACTED);
+                }
+                i = end;
+            }
+            None => {
+                let c = text[i..].chars().next().unwrap();
+                result.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+    result
+}
+
+/// So `monkey=` isn't treated as `key=`
+fn follows_word(text: &str, i: usize) -> bool {
+    text[..i].chars().last().is_some_and(|c| c.is_alphanumeric())
+}
+
+struct RequestStart(Instant);
+
+/// Writes one line per request with its id, so it can be matched with the upstream calls it made
+pub struct RequestLogFairing {}
+
+#[rocket::async_trait]
+impl Fairing for RequestLogFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request log",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let started = request.local_cache(|| RequestStart(Instant::now()));
+        let id = request_id(request);
+        let level = if response.status().code >= 500 { Level::Error } else { Level::Info };
+        log(level, Some(&id), "request", &[
+            ("method", request.method().to_string()),
+            ("path", request.uri().path().to_string()),
+            ("status", response.status().code.to_string()),
+            ("elapsedMs", started.0.elapsed().as_millis().to_string()),
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact("GET https://youtube/videos?part=id&key=AIza123&id=1"), "GET https://youtube/videos?part=id&key=REDACTED&id=1");
+        assert_eq!(redact("error sending request for url (https://youtube/search?key=AIza123): timed out"), "error sending request for url (https://youtube/search?key=REDACTED): timed out");
+        assert_eq!(redact("X-Api-Key: secret"), "X-Api-Key: REDACTED");
+        assert_eq!(redact("Authorization: Bearer abc.def\nHost: local"), "Authorization: REDACTED\nHost: local");
+        assert_eq!(redact("monkey=banana"), "monkey=banana");
+    }
+
+    #[test]
+    fn test_format_line() {
+        //GIVEN a message with a key in a field
+        let fields = [("url", String::from("/videos?key=AIza123"))];
+        //WHEN formatted as logfmt and json
+        let logfmt = format_line(Level::Warn, Some("abc-0001"), "upstream failed", &fields, false);
+        let json = format_line(Level::Warn, Some("abc-0001"), "upstream failed", &fields, true);
+        //THEN the request id is included and the key isn't
+        assert!(logfmt.contains(r#" level=warn msg="upstream failed" requestId=abc-0001 url="/videos?key=REDACTED""#));
+        assert!(json.contains(r#""requestId":"abc-0001""#));
+        assert!(json.contains(r#""url":"/videos?key=REDACTED""#));
+    }
+}
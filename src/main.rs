@@ -1,20 +1,36 @@
-#![feature(proc_macro_hygiene, decl_macro)]
 #[macro_use]
 extern crate rocket;
 
-use anyhow::{Result, Context};
+use anyhow::{Context, Result};
 use dotenv::dotenv;
-use std::env;
 use crate::key_manager::{KeyManager};
+use crate::key_store::KeyStore;
 use crate::youtube_manager::YoutubeManager;
 use crate::youtube_client::YOUTUBE_URL;
-use rocket::{State, Config, Request, Rocket};
-use rocket::config::{Environment};
-use rocket_contrib::json::Json;
+use rocket::{Build, State, Config as RocketConfig, Request, Rocket};
+use rocket::config::LogLevel;
+use rocket::serde::json::Json;
 use std::collections::HashMap;
+use std::net::IpAddr;
 use rocket::request::{FromRequest, Outcome};
 use rocket::http::Status;
-use chrono::{Utc, SecondsFormat};
+use crate::retry::RequestDiagnostic;
+use crate::models::key_status::KeyStatus;
+use crate::models::reconciliation::Reconciliation;
+use crate::clients::{ClientEntry, ClientQuota, ClientRegistry, ClientStatus, Scope};
+use std::sync::Arc;
+use crate::key_hash::KeyHasher;
+use crate::tokens::TokenVerifier;
+use crate::metrics::{METRICS, MetricsFairing};
+use crate::logger::RequestLogFairing;
+use rocket::http::ContentType;
+use rocket::response::status::Custom;
+use crate::models::health::{HealthReport, HealthStatus};
+use crate::audit::AuditLog;
+use crate::usage::{UsageLedger, UsageFairing};
+use crate::config::{Args, Config, redact_url};
+use crate::cli::Command;
+use structopt::StructOpt;
 
 mod endpoints;
 mod models;
@@ -23,51 +39,160 @@ mod youtube_manager;
 mod date_util;
 mod youtube_client;
 mod timer;
+mod cache;
+mod live_chat;
+mod error;
+mod request_id;
+mod retry;
+mod key_store;
+mod quota;
+mod clients;
+mod request_context;
+mod key_hash;
+mod auth_failures;
+mod tokens;
+mod metrics;
+mod logger;
+mod health;
+mod audit;
+mod usage;
+mod config;
+mod cli;
+mod http_client;
+mod budget;
 
-fn main() -> Result<()> {
+#[rocket::main]
+async fn main() {
     dotenv().ok();
+    let args = Args::from_args();
+    if let Err(error) = run(args).await {
+        logger::error(None, "Exiting", &[("error", format!("{:#}", error))]);
+        std::process::exit(1);
+    }
+}
 
-    let port: u16 = env::var("PORT").unwrap_or(String::from("3001")).parse().context("Invalid PORT").unwrap();
-    let proxy = env::var("PROXY").ok();
-    let youtube_keys = env::var("YOUTUBE_API_KEYS").context("Invalid/Missing YOUTUBE_API_KEYS").unwrap().split(",").map(|item| item.to_string()).collect();
-    let api_key = if env::var_os("API_KEY").is_some() {
-        let key = env::var("API_KEY").context("Invalid API_KEY").unwrap();
-        Some(key)
-    } else {
-        None
-    };
+async fn run(mut args: Args) -> Result<()> {
+    let config = Config::load(&args)?;
+    if args.check_config {
+        println!("{}", toml::to_string(&config.redacted())?);
+        return Ok(());
+    }
+    logger::init(config.get_log_level(), config.is_json_logging());
+    timer::init(config.reset.hour, config.reset.minute);
 
-    let key_manager = KeyManager::new(youtube_keys);
-    let youtube_manager = YoutubeManager::new(key_manager, YOUTUBE_URL.to_string(), &proxy);
+    match args.command.take() {
+        None | Some(Command::Serve) => serve(&config).await,
+        Some(command) => cli::run(command, &config, &args).await
+    }
+}
+
+async fn serve(config: &Config) -> Result<()> {
+    let clients = Arc::new(make_clients(config)?);
+    let youtube_manager = make_youtube_manager(config)?;
 
-    let date = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
-    println!("Starting youtube proxy server on {} at {}", port, date);
+    logger::info(None, "Starting youtube proxy server", &[("address", config.server.address.clone()), ("port", config.server.port.to_string())]);
 
-    if let Some(proxy) = proxy {
-        println!("Proxying requests via {}", proxy);
+    if let Some(proxy) = &config.youtube.proxy {
+        logger::info(None, "Proxying requests", &[("proxy", redact_url(proxy)), ("noProxy", config.youtube.no_proxy.join(","))]);
     }
 
-    let config = Config::build(Environment::active()?)
-        .address("0.0.0.0")
-        .port(port)
-        .finalize()?;
+    // requests are logged by RequestLogFairing, Rocket's own lines aren't structured
+    let rocket_config = RocketConfig {
+        address: config.server.address.parse::<IpAddr>().context("Invalid address")?,
+        port: config.server.port,
+        log_level: LogLevel::Critical,
+        ip_header: Some(config.server.ip_header.clone().into()),
+        ..RocketConfig::default()
+    };
 
-    youtube_manager.start_reset_timer();
+    youtube_manager.start_reset_timer(clients.clone());
+    youtube_manager.start_key_reload_listener()?;
+    youtube_manager.start_usage_saving();
+    ClientRegistry::start_reload_on_hangup(clients.clone())?;
 
-    make_rocket(config, api_key, youtube_manager).launch();
+    make_rocket(rocket_config, clients, youtube_manager).launch().await?;
 
     Ok(())
 }
 
-fn make_rocket(config: Config, api_key: Option<String>, youtube_manager: YoutubeManager) -> Rocket {
-    return rocket::custom(config)
+/// Shared by the server and the CLI commands
+fn make_youtube_manager(config: &Config) -> Result<YoutubeManager> {
+    let audit_log = match &config.audit.dir {
+        Some(dir) => {
+            logger::info(None, "Recording upstream calls", &[("path", dir.display().to_string()), ("retentionDays", config.audit.retention_days.to_string())]);
+            Some(AuditLog::new(dir.clone(), config.audit.retention_days)?)
+        }
+        None => None
+    };
+    let usage = UsageLedger::new(Some(config.usage.file.clone()))?;
+
+    let key_store = config.youtube.get_keys_file().map(KeyStore::new);
+    let mut key_manager = match &key_store {
+        Some(store) if store.exists() => {
+            logger::info(None, "Loading keys", &[("path", store.path().display().to_string())]);
+            if !config.youtube.keys.is_empty() {
+                logger::warn(None, "Key file is used instead of YOUTUBE_API_KEYS", &[("path", store.path().display().to_string())]);
+            }
+            KeyManager::from_entries(store.load()?)
+        }
+        _ => KeyManager::new(config.youtube.keys.clone())
+    };
+    key_manager.set_reconciliation_mode(config.youtube.quota_reconciliation);
+    YoutubeManager::new(key_manager, YOUTUBE_URL.to_string(), config, key_store, audit_log, usage)
+}
+
+fn make_clients(config: &Config) -> Result<ClientRegistry> {
+    let clients_file = &config.auth.clients_file;
+    let mut client_entries = if clients_file.exists() {
+        logger::info(None, "Loading clients", &[("path", clients_file.display().to_string())]);
+        ClientRegistry::load(clients_file)?
+    } else {
+        vec![]
+    };
+    if let Some(key) = &config.auth.api_key {
+        if !client_entries.iter().any(|entry| entry.name == "default") {
+            client_entries.push(ClientEntry { name: String::from("default"), key: Some(key.clone()), key_hash: String::new(), scopes: Scope::all(), daily_budget: None, rate_limit: None });
+        }
+    }
+    let pepper = config.auth.pepper.clone().unwrap_or_default();
+    if pepper.is_empty() && !client_entries.is_empty() {
+        logger::warn(None, "API_KEY_PEPPER isn't set, client keys will be hashed without a pepper", &[]);
+    }
+    let mut clients = ClientRegistry::new(client_entries, Some(clients_file.clone()), KeyHasher::new(pepper));
+    // checked by Config::validate
+    clients.set_trusted_proxies(config.server.trusted_proxies.iter().filter_map(|proxy| proxy.parse().ok()).collect());
+    let issuer = config.auth.jwt_issuer.clone();
+    let audience = config.auth.jwt_audience.clone();
+    if let Some(secret) = &config.auth.jwt_secret {
+        logger::info(None, "Accepting bearer tokens", &[("algorithm", String::from("HS256"))]);
+        clients.set_token_verifier(TokenVerifier::hs256(secret, issuer, audience));
+    } else if let Some(public_key_file) = &config.auth.jwt_public_key_file {
+        logger::info(None, "Accepting bearer tokens", &[("algorithm", String::from("RS256"))]);
+        clients.set_token_verifier(TokenVerifier::rs256(public_key_file, issuer, audience)?);
+    }
+    Ok(clients)
+}
+
+fn make_rocket(config: RocketConfig, clients: Arc<ClientRegistry>, youtube_manager: YoutubeManager) -> Rocket<Build> {
+    rocket::custom(config)
         .manage(youtube_manager)
-        .manage(api_key)
-        .mount("/", routes![alive, status, reset_quotas,
+        .manage(clients)
+        .attach(MetricsFairing {})
+        .attach(RequestLogFairing {})
+        .attach(UsageFairing {})
+        .mount("/", routes![alive, health_live, health_ready, render_metrics, status, client_status, reset_quotas, purge_cache, diagnostics, reconciliation,
             endpoints::search::channel, endpoints::search::video, endpoints::search::playlist,
             endpoints::single::channel, endpoints::single::video, endpoints::single::playlist,
             endpoints::videos::get_videos_for_channel,
-            endpoints::videos::get_most_recent_videos_for_channel, endpoints::videos::get_videos_for_playlist]);
+            endpoints::videos::get_most_recent_videos_for_channel, endpoints::videos::get_videos_for_playlist,
+            endpoints::videos::get_activities_for_channel,
+            endpoints::videos::get_live_videos_for_channel, endpoints::videos::get_upcoming_videos_for_channel,
+            endpoints::keys::add, endpoints::keys::remove, endpoints::keys::relabel,
+            endpoints::keys::disable, endpoints::keys::enable,
+            endpoints::clients::add, endpoints::clients::revoke, endpoints::spend::spend, endpoints::usage::usage,
+            endpoints::chat::stream, endpoints::trending::trending,
+            endpoints::meta::categories, endpoints::meta::regions, endpoints::meta::languages])
+        .register("/", catchers![error::bad_request, error::unauthorized, error::forbidden, error::not_found, error::unprocessable, error::too_many_requests, error::internal_error])
 }
 
 #[get("/alive")]
@@ -75,43 +200,118 @@ fn alive() -> &'static str {
     "OK"
 }
 
+#[get("/health/live")]
+fn health_live() -> &'static str {
+    "OK"
+}
+
+/// 200 when ok or degraded so traffic keeps flowing while a key pool is low, 503 when unhealthy
+#[get("/health/ready")]
+async fn health_ready(youtube_manager: &State<YoutubeManager>) -> Custom<Json<HealthReport>> {
+    let report = youtube_manager.get_health().await;
+    let code = if report.get_status() == HealthStatus::Unhealthy { Status::ServiceUnavailable } else { Status::Ok };
+    Custom(code, Json(report))
+}
+
+#[get("/metrics")]
+async fn render_metrics(youtube_manager: &State<YoutubeManager>, _admin_key: AdminKey) -> (ContentType, String) {
+    (ContentType::Plain, METRICS.render(&youtube_manager.get_quota_usage().await))
+}
+
 #[get("/v1/admin/status")]
-fn status(youtube_manager: State<YoutubeManager>, _api_key: ApiKey) -> Json<HashMap<usize, usize>> {
-    Json(youtube_manager.get_key_status())
+async fn status(youtube_manager: &State<YoutubeManager>, _admin_key: AdminKey) -> Json<HashMap<usize, KeyStatus>> {
+    Json(youtube_manager.get_key_status().await)
+}
+
+#[get("/v1/admin/clients")]
+fn client_status(clients: &State<Arc<ClientRegistry>>, _admin_key: AdminKey) -> Json<HashMap<String, ClientStatus>> {
+    Json(clients.get_status())
+}
+
+#[get("/v1/admin/diagnostics")]
+fn diagnostics(youtube_manager: &State<YoutubeManager>, _admin_key: AdminKey) -> Json<Vec<RequestDiagnostic>> {
+    Json(youtube_manager.get_diagnostics())
+}
+
+#[get("/v1/admin/quotas/reconciliation")]
+async fn reconciliation(youtube_manager: &State<YoutubeManager>, _admin_key: AdminKey) -> Json<Reconciliation> {
+    Json(youtube_manager.get_reconciliation().await)
+}
+
+#[post("/v1/admin/cache/purge")]
+fn purge_cache(youtube_manager: &State<YoutubeManager>, _admin_key: AdminKey) -> Status {
+    youtube_manager.purge_caches();
+    Status::Ok
 }
 
 #[post("/v1/admin/quotas/reset")]
-fn reset_quotas(youtube_manager: State<YoutubeManager>, _api_key: ApiKey) -> Status {
-    youtube_manager.reset_key_status();
+async fn reset_quotas(youtube_manager: &State<YoutubeManager>, clients: &State<Arc<ClientRegistry>>, _admin_key: AdminKey) -> Status {
+    youtube_manager.reset_key_status().await;
+    clients.reset();
     Status::Ok
 }
 
-pub struct ApiKey {}
+pub struct ApiKey {
+    client: Option<Arc<ClientQuota>>,
+}
+
+impl ApiKey {
+    pub fn get_client(&self) -> Option<Arc<ClientQuota>> {
+        self.client.clone()
+    }
+}
 
-impl<'a, 'r> FromRequest<'a, 'r> for ApiKey {
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
     type Error = String;
 
-    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
-        let api_key_outcome = request.guard::<State<Option<String>>>().unwrap();
-        if api_key_outcome.is_none() { return Outcome::Success(ApiKey {}); }
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let clients = match request.guard::<&State<Arc<ClientRegistry>>>().await {
+            Outcome::Success(clients) => clients,
+            _ => return Outcome::Error((Status::InternalServerError, "Missing client registry".to_string()))
+        };
+        if clients.is_open() { return Outcome::Success(ApiKey { client: None }); }
 
-        let keys: Vec<_> = request.headers().get("x-api-key").collect();
-        let api_key = api_key_outcome.as_ref().unwrap().to_string();
-        if keys.len() >= 1 && keys[0] == api_key {
-            Outcome::Success(ApiKey {})
-        } else {
-            Outcome::Failure((Status::Unauthorized, format!("Server Error")))
+        let ip = clients.lockout_address(request.remote().map(|remote| remote.ip()), request.client_ip());
+        let token = request.headers().get_one("Authorization").and_then(|value| value.strip_prefix("Bearer "));
+        let result = clients.authenticate(request.headers().get_one("x-api-key"), token, ip)
+            .and_then(|client| client.check_rate().map(|_| client));
+        match result {
+            Ok(client) => Outcome::Success(ApiKey { client: Some(client) }),
+            Err(error) => {
+                let status = error.get_status();
+                error::set_guard_error(request, error);
+                Outcome::Error((status, "Server Error".to_string()))
+            }
         }
     }
 }
 
+/// Same as [ApiKey] but the client must also have the admin scope
+pub struct AdminKey {}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminKey {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        request.guard::<ApiKey>().await.and_then(|api_key| {
+            match api_key.get_client() {
+                Some(client) if !client.has_scope(Scope::Admin) => Outcome::Error((Status::Forbidden, "Missing admin scope".to_string())),
+                _ => Outcome::Success(AdminKey {})
+            }
+        })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use mockito;
-    use rocket::local::Client;
+    use rocket::local::blocking::Client;
     use lazy_static::lazy_static;
     use mockito::{mock, Matcher};
+    use rocket::http::Header;
+    use std::env;
     use std::path::PathBuf;
 
     lazy_static! {
@@ -119,13 +319,26 @@ mod test {
         static ref TEST_API_KEY: Option<String> = Some(String::from("test"));
     }
 
+    fn test_config() -> Config {
+        let mut config = Config::default();
+        config.youtube.proxy = env::var("PROXY").ok();
+        config.cache.trending_ttl = 60;
+        config
+    }
+
     //Make client
     fn make_client(keys: Vec<&'static str>, api_key: Option<String>) -> Client {
+        let clients = api_key.into_iter()
+            .map(|key| ClientEntry { name: String::from("default"), key: Some(key), key_hash: String::new(), scopes: Scope::all(), daily_budget: None, rate_limit: None })
+            .collect();
+        make_client_with_clients(keys, clients)
+    }
+
+    fn make_client_with_clients(keys: Vec<&'static str>, clients: Vec<ClientEntry>) -> Client {
         dotenv().ok();
         let key_manager = KeyManager::new_test(keys);
-        let youtube_manager = YoutubeManager::new(key_manager, mockito::server_url().clone(), &env::var("PROXY").ok());
-        let client = Client::new(make_rocket(Config::development(), api_key, youtube_manager)).expect("valid rocket instance");
-        client
+        let youtube_manager = YoutubeManager::new(key_manager, mockito::server_url().clone(), &test_config(), None, None, UsageLedger::new(None).unwrap()).unwrap();
+        Client::tracked(make_rocket(RocketConfig::debug_default(), Arc::new(ClientRegistry::new(clients, None, KeyHasher::new(String::from("pepper")))), youtube_manager)).expect("valid rocket instance")
     }
 
     #[test]
@@ -133,10 +346,10 @@ mod test {
         //GIVEN client with default keys
         let client = make_client(DEFAULT_KEYS.clone(), None);
         //WHEN making /alive request
-        let mut response = client.get("/alive").dispatch();
+        let response = client.get("/alive").dispatch();
         //THEN response is ok
         assert_eq!(response.status(), Status::Ok);
-        assert_eq!(response.body_string(), Some("OK".into()));
+        assert_eq!(response.into_string(), Some("OK".into()));
     }
 
     #[test]
@@ -155,15 +368,445 @@ mod test {
         assert_eq!(search_playlist.status(), Status::Unauthorized);
     }
 
+    #[test]
+    fn test_unauthorized_error_body() {
+        //GIVEN client with default keys and the api key is set
+        let client = make_client(DEFAULT_KEYS.clone(), TEST_API_KEY.clone());
+        //WHEN making a request without the api key
+        let response = client.get("/v1/admin/status").dispatch();
+        //THEN response is a json error
+        assert_eq!(response.status(), Status::Unauthorized);
+        let body = response.into_string().unwrap();
+        assert!(body.contains(r#""code":"unauthorized""#));
+        assert!(body.contains(r#""requestId":""#));
+    }
+
+    #[test]
+    fn test_client_rate_limit_and_budget() {
+        //GIVEN client limited to 3 requests a minute and a budget of one search
+        let entry = ClientEntry { name: String::from("app"), key: Some(String::from("secret")), key_hash: String::new(), scopes: Scope::all(), daily_budget: Some(100), rate_limit: Some(3) };
+        let json = load_test_file("search_result_channel.json");
+        let _mock = mock("GET", Matcher::Regex(r"/search\?.*".to_string())).with_body(json).expect(1).create();
+        let client = make_client_with_clients(DEFAULT_KEYS.clone(), vec![entry]);
+        //WHEN searching until both limits are hit
+        let first = client.get("/v1/search/channel?q=test").header(Header::new("x-api-key", "secret")).dispatch();
+        let over_budget = client.get("/v1/search/channel?q=test").header(Header::new("x-api-key", "secret")).dispatch();
+        let clients = client.get("/v1/admin/clients").header(Header::new("x-api-key", "secret")).dispatch();
+        let over_rate = client.get("/v1/search/channel?q=test").header(Header::new("x-api-key", "secret")).dispatch();
+        //THEN budget and rate limit are reported with when to retry
+        assert_eq!(first.status(), Status::Ok);
+        assert_eq!(over_budget.status(), Status::TooManyRequests);
+        assert!(over_budget.headers().get_one("Retry-After").is_some());
+        assert!(over_budget.into_string().unwrap().contains(r#""code":"client_quota_exhausted""#));
+        assert_eq!(clients.into_string(), Some(r#"{"app":{"scopes":["read","search","admin"],"spent":100,"requests":3,"dailyBudget":100,"remaining":0}}"#.into()));
+        assert_eq!(over_rate.status(), Status::TooManyRequests);
+        assert!(over_rate.headers().get_one("Retry-After").is_some());
+        assert!(over_rate.into_string().unwrap().contains(r#""code":"rate_limited""#));
+        _mock.assert();
+    }
+
+    #[test]
+    fn test_client_scopes_and_revocation() {
+        //GIVEN an admin client and a read only client
+        let admin = ClientEntry { name: String::from("admin"), key: Some(String::from("admin-secret")), key_hash: String::new(), scopes: Scope::all(), daily_budget: None, rate_limit: None };
+        let reader = ClientEntry { name: String::from("reader"), key: Some(String::from("read-secret")), key_hash: String::new(), scopes: vec![Scope::Read], daily_budget: None, rate_limit: None };
+        let client = make_client_with_clients(DEFAULT_KEYS.clone(), vec![admin, reader]);
+        //WHEN the read only client uses admin and search endpoints, then is revoked
+        let admin_as_reader = client.get("/v1/admin/status").header(Header::new("x-api-key", "read-secret")).dispatch();
+        let search_as_reader = client.get("/v1/search/video?q=test").header(Header::new("x-api-key", "read-secret")).dispatch();
+        let revoked = client.delete("/v1/admin/clients/reader").header(Header::new("x-api-key", "admin-secret")).dispatch();
+        let after_revoke = client.get("/v1/meta/regions").header(Header::new("x-api-key", "read-secret")).dispatch();
+        let added = client.post("/v1/admin/clients").header(ContentType::JSON).header(Header::new("x-api-key", "admin-secret"))
+            .body(r#"{"name":"new","key":"new-secret","scopes":["admin"]}"#).dispatch();
+        let admin_as_new = client.get("/v1/admin/status").header(Header::new("x-api-key", "new-secret")).dispatch();
+        //THEN scopes are enforced and revoked keys stop working straight away
+        assert_eq!(admin_as_reader.status(), Status::Forbidden);
+        assert!(admin_as_reader.into_string().unwrap().contains(r#""code":"forbidden""#));
+        assert_eq!(search_as_reader.status(), Status::Forbidden);
+        assert_eq!(revoked.status(), Status::Ok);
+        assert_eq!(after_revoke.status(), Status::Unauthorized);
+        assert_eq!(added.status(), Status::Created);
+        assert_eq!(admin_as_new.status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_repeated_auth_failures_are_rate_limited() {
+        //GIVEN client with the api key set
+        let client = make_client(DEFAULT_KEYS.clone(), TEST_API_KEY.clone());
+        let remote: std::net::SocketAddr = "10.1.2.3:4000".parse().unwrap();
+        //WHEN one address keeps sending the wrong key
+        let statuses: Vec<Status> = (0..11)
+            .map(|_| client.get("/v1/admin/status").header(Header::new("x-api-key", "wrong")).remote(remote).dispatch().status())
+            .collect();
+        let correct_key = client.get("/v1/admin/status").header(Header::new("x-api-key", "test")).remote(remote).dispatch();
+        //THEN it's refused until the window passes, even with the right key
+        assert_eq!(statuses[0], Status::Unauthorized);
+        assert_eq!(statuses[10], Status::TooManyRequests);
+        assert_eq!(correct_key.status(), Status::TooManyRequests);
+        assert!(correct_key.into_string().unwrap().contains(r#""code":"rate_limited""#));
+    }
+
+    #[test]
+    fn test_spoofed_ip_header_is_ignored_by_lockout() {
+        //GIVEN client with the api key set and a victim address
+        let client = make_client(DEFAULT_KEYS.clone(), TEST_API_KEY.clone());
+        let attacker: std::net::SocketAddr = "10.1.2.3:4000".parse().unwrap();
+        let victim: std::net::SocketAddr = "10.9.9.9:4000".parse().unwrap();
+        //WHEN the attacker sends wrong keys claiming a new address each time, then the victim's address
+        let rotating: Vec<Status> = (0..11)
+            .map(|i| client.get("/v1/admin/status").header(Header::new("x-api-key", "wrong")).header(Header::new("X-Real-IP", format!("10.5.0.{}", i))).remote(attacker).dispatch().status())
+            .collect();
+        for _ in 0..11 {
+            client.get("/v1/admin/status").header(Header::new("x-api-key", "wrong")).header(Header::new("X-Real-IP", "10.9.9.9")).remote(attacker).dispatch();
+        }
+        let victim_request = client.get("/v1/admin/status").header(Header::new("x-api-key", "test")).remote(victim).dispatch();
+        //THEN the attacker is still locked out and the victim isn't
+        assert_eq!(rotating[10], Status::TooManyRequests);
+        assert_eq!(victim_request.status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_lockout_uses_ip_header_from_trusted_proxy() {
+        //GIVEN client with the api key set behind a trusted proxy
+        dotenv().ok();
+        let entry = ClientEntry { name: String::from("default"), key: TEST_API_KEY.clone(), key_hash: String::new(), scopes: Scope::all(), daily_budget: None, rate_limit: None };
+        let mut clients = ClientRegistry::new(vec![entry], None, KeyHasher::new(String::from("pepper")));
+        clients.set_trusted_proxies(vec!["10.0.0.1".parse().unwrap()]);
+        let youtube_manager = YoutubeManager::new(KeyManager::new_test(DEFAULT_KEYS.clone()), mockito::server_url(), &test_config(), None, None, UsageLedger::new(None).unwrap()).unwrap();
+        let client = Client::tracked(make_rocket(RocketConfig::debug_default(), Arc::new(clients), youtube_manager)).expect("valid rocket instance");
+        let proxy: std::net::SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        //WHEN one caller sends wrong keys through the proxy, then another caller sends the right key
+        let attacker: Vec<Status> = (0..11)
+            .map(|_| client.get("/v1/admin/status").header(Header::new("x-api-key", "wrong")).header(Header::new("X-Real-IP", "10.5.0.1")).remote(proxy).dispatch().status())
+            .collect();
+        let other = client.get("/v1/admin/status").header(Header::new("x-api-key", "test")).header(Header::new("X-Real-IP", "10.5.0.2")).remote(proxy).dispatch();
+        //THEN only the first caller is locked out
+        assert_eq!(attacker[10], Status::TooManyRequests);
+        assert_eq!(other.status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_bearer_tokens() {
+        //GIVEN client accepting tokens for the proxy audience
+        dotenv().ok();
+        let mut clients = ClientRegistry::new(vec![], None, KeyHasher::new(String::from("pepper")));
+        clients.set_token_verifier(TokenVerifier::hs256("jwt-secret", Some(String::from("backend")), Some(String::from("proxy"))));
+        let youtube_manager = YoutubeManager::new(KeyManager::new_test(DEFAULT_KEYS.clone()), mockito::server_url(), &test_config(), None, None, UsageLedger::new(None).unwrap()).unwrap();
+        let client = Client::tracked(make_rocket(RocketConfig::debug_default(), Arc::new(clients), youtube_manager)).expect("valid rocket instance");
+        let admin_token = tokens::test::make_token("jwt-secret", vec!["admin"], "proxy", 60);
+        let read_token = tokens::test::make_token("jwt-secret", vec!["read"], "proxy", 60);
+        let expired_token = tokens::test::make_token("jwt-secret", vec!["admin"], "proxy", -120);
+        //WHEN calling an admin endpoint with each token
+        let admin = client.get("/v1/admin/status").header(Header::new("Authorization", format!("Bearer {}", admin_token))).dispatch();
+        let read = client.get("/v1/admin/status").header(Header::new("Authorization", format!("Bearer {}", read_token))).dispatch();
+        let expired = client.get("/v1/admin/status").header(Header::new("Authorization", format!("Bearer {}", expired_token))).dispatch();
+        let missing = client.get("/v1/admin/status").dispatch();
+        //THEN token scopes and expiry are enforced
+        assert_eq!(admin.status(), Status::Ok);
+        assert_eq!(read.status(), Status::Forbidden);
+        assert_eq!(expired.status(), Status::Unauthorized);
+        assert_eq!(missing.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn test_metrics() {
+        run_resource_test("single_result_empty.json", r"/videos\?.*", || {
+            //GIVEN client with default keys that has handled a request
+            let client = make_client(DEFAULT_KEYS.clone(), None);
+            client.get("/v1/video/missing").dispatch();
+            //WHEN requesting metrics
+            let response = client.get("/metrics").dispatch();
+            //THEN the request, the upstream call and the keys are included
+            assert_eq!(response.status(), Status::Ok);
+            let body = response.into_string().unwrap();
+            assert!(body.contains(r#"http_request_duration_seconds_count{route="/v1/video/<id>",status="404"}"#));
+            assert!(body.contains(r#"youtube_request_duration_seconds_count{resource="videos",status="200"}"#));
+            assert!(body.contains(r#"youtube_key_quota_remaining{key="key0"}"#));
+        });
+    }
+
+    #[test]
+    fn test_health() {
+        //GIVEN client with one key and the reset timer running
+        let client = make_client(vec!["key1"], None);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            client.rocket().state::<YoutubeManager>().unwrap().start_reset_timer(Arc::new(ClientRegistry::new(vec![], None, KeyHasher::new(String::new()))));
+        });
+        //WHEN checking health before and after the key is exhausted
+        let live = client.get("/health/live").dispatch();
+        let ready = client.get("/health/ready").dispatch();
+        client.post("/v1/admin/keys/0/disable").dispatch();
+        let unhealthy = client.get("/health/ready").dispatch();
+        //THEN readiness reflects the key pool
+        assert_eq!(live.status(), Status::Ok);
+        assert_eq!(ready.status(), Status::Ok);
+        assert!(ready.into_string().unwrap().starts_with(r#"{"status":"ok","#));
+        assert_eq!(unhealthy.status(), Status::ServiceUnavailable);
+        let body = unhealthy.into_string().unwrap();
+        assert!(body.starts_with(r#"{"status":"unhealthy","#));
+        assert!(body.contains(r#""keys":{"status":"unhealthy","message":"No keys have quota left"}"#));
+        assert!(body.contains(r#""resetTimer":{"status":"ok","message":"Running"}"#));
+    }
+
+    #[test]
+    fn test_spend_is_audited() {
+        run_resource_test("search_result_channel.json", r"/search\?.*", || {
+            //GIVEN client recording upstream calls to a temp dir
+            dotenv().ok();
+            let mut dir = std::env::temp_dir();
+            dir.push(format!("audit_main_test_{}", std::process::id()));
+            let audit_log = AuditLog::new(dir.clone(), 30).unwrap();
+            let youtube_manager = YoutubeManager::new(KeyManager::new_test(DEFAULT_KEYS.clone()), mockito::server_url(), &test_config(), None, Some(audit_log), UsageLedger::new(None).unwrap()).unwrap();
+            let clients = vec![ClientEntry { name: String::from("web"), key: Some(String::from("test")), key_hash: String::new(), scopes: Scope::all(), daily_budget: None, rate_limit: None }];
+            let client = Client::tracked(make_rocket(RocketConfig::debug_default(), Arc::new(ClientRegistry::new(clients, None, KeyHasher::new(String::new()))), youtube_manager)).expect("valid rocket instance");
+            //WHEN searching and then asking for this month's spend
+            client.get("/v1/search/channel?q=test").header(Header::new("x-api-key", "test")).dispatch();
+            let spend = client.get("/v1/admin/spend?by=month").header(Header::new("x-api-key", "test")).dispatch();
+            let invalid = client.get("/v1/admin/spend?by=week").header(Header::new("x-api-key", "test")).dispatch();
+            let too_far_back = client.get("/v1/admin/spend?months_back=4294967295&months=24").header(Header::new("x-api-key", "test")).dispatch();
+            std::fs::remove_dir_all(&dir).unwrap();
+            //THEN the search is billed to the client, route and key
+            assert_eq!(spend.status(), Status::Ok);
+            let body = spend.into_string().unwrap();
+            assert!(body.contains(r#""cost":100,"calls":1,"#));
+            assert!(body.contains(r#""byClient":{"web":100}"#));
+            assert!(body.contains(r#""byRoute":{"/v1/search/channel":100}"#));
+            assert!(body.contains(r#""byKey":{"key0":100}"#));
+            assert_eq!(invalid.status(), Status::BadRequest);
+            assert_eq!(too_far_back.status(), Status::BadRequest);
+        });
+    }
+
+    #[test]
+    fn test_trending() {
+        //GIVEN client with default keys and youtube returning a page of popular videos for GB
+        let path = r"/videos\?.*chart=mostPopular.*regionCode=GB.*pageToken=CAIQAA.*";
+        let _mock = mock("GET", Matcher::Regex(path.to_string())).with_body(load_test_file("trending_result.json")).expect(1).create();
+        let client = make_client(DEFAULT_KEYS.clone(), None);
+        //WHEN requesting the page twice with a lowercase region
+        let first = client.get("/v1/trending?region=gb&page_token=CAIQAA").dispatch();
+        let second = client.get("/v1/trending?region=gb&page_token=CAIQAA").dispatch();
+        //THEN both have the videos and next page token but youtube is only called once
+        assert_eq!(first.status(), Status::Ok);
+        let body = first.into_string().unwrap();
+        assert!(body.contains(r#""id":"trend1""#));
+        assert!(body.contains(r#""id":"trend2""#));
+        assert!(body.contains(r#""views":1200345"#));
+        assert!(body.contains(r#""nextPageToken":"CAIQAA""#));
+        assert_eq!(second.into_string().unwrap(), body);
+        _mock.assert();
+    }
+
+    #[test]
+    fn test_activities() {
+        //GIVEN client with default keys and youtube returning an upload, a playlist addition and a like for the date range
+        let path = r"/activities\?.*channelId=UCactivitychannel.*publishedAfter=2020-06-01T00%3A00%3A00Z.*publishedBefore=2020-06-30T00%3A00%3A00Z.*";
+        let _mock = mock("GET", Matcher::Regex(path.to_string())).with_body(load_test_file("activities_result.json")).create();
+        let client = make_client(DEFAULT_KEYS.clone(), None);
+        //WHEN requesting the channel's activities for June
+        let response = client.get("/v1/channel/UCactivitychannel/activities?published_after=2020-06-01T00:00:00Z&published_before=2020-06-30T00:00:00Z").dispatch();
+        //THEN each activity has its type and the video it's about
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+        assert!(body.contains(r#""id":"activity-upload","type":"upload""#));
+        assert!(body.contains(r#""video":{"id":"upload1""#));
+        assert!(body.contains(r#""id":"activity-playlist","type":"playlistItem""#));
+        assert!(body.contains(r#""video":{"id":"added1""#));
+        assert!(body.contains(r#""playlistId":"PLactivity""#));
+        assert!(body.contains(r#""id":"activity-like","type":"like""#));
+        assert!(body.contains(r#""video":{"id":"liked1""#));
+        _mock.assert();
+    }
+
+    #[test]
+    fn test_live_videos_have_live_details() {
+        //GIVEN client with default keys, youtube searching live videos and returning details for one of them
+        let search_path = r"/search\?.*channelId=UClivechannel.*eventType=live.*";
+        let _search = mock("GET", Matcher::Regex(search_path.to_string())).with_body(load_test_file("search_result_live.json")).create();
+        let details_path = r"/videos\?.*liveStreamingDetails.*id=live1%2Clive2.*";
+        let _details = mock("GET", Matcher::Regex(details_path.to_string())).with_body(load_test_file("live_details_result.json")).create();
+        let client = make_client(DEFAULT_KEYS.clone(), None);
+        //WHEN requesting the channel's live videos
+        let response = client.get("/v1/channel/UClivechannel/live").dispatch();
+        //THEN the details are merged into the matching video only
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+        assert!(body.contains(r#""live":{"scheduledStart":"2020-06-03T10:00:00Z","actualStart":"2020-06-03T10:02:11Z","concurrentViewers":1523,"activeChatId":"Cg0KC2xpdmUxKicKGFVDbGl2ZWNoYW5uZWw"}"#));
+        assert_eq!(body.matches(r#""live":"#).count(), 1);
+        assert!(body.contains(r#""id":"live2""#));
+        _search.assert();
+        _details.assert();
+    }
+
+    #[test]
+    fn test_usage() {
+        //GIVEN client with default keys and youtube returning categories and search results
+        let json = load_test_file("categories_result.json");
+        let _categories = mock("GET", Matcher::Regex(r"/videoCategories\?.*".to_string())).with_body(json).create();
+        let _search = mock("GET", Matcher::Regex(r"/search\?.*".to_string())).with_body(load_test_file("search_result_channel.json")).create();
+        let client = make_client(DEFAULT_KEYS.clone(), None);
+        //WHEN searching, requesting categories twice and then asking for usage
+        client.get("/v1/search/channel?q=Test").dispatch();
+        client.get("/v1/meta/categories?region=gb").dispatch();
+        client.get("/v1/meta/categories?region=gb").dispatch();
+        let usage = client.get("/v1/admin/usage?months_back=1").dispatch();
+        let invalid = client.get("/v1/admin/usage?months_back=100").dispatch();
+        //THEN this month has the spend, requests, cache hit and query, last month is empty
+        assert_eq!(usage.status(), Status::Ok);
+        let body = usage.into_string().unwrap();
+        assert!(body.contains(r#""quotaSpent":101,"requestsServed":3,"cacheHits":1,"cacheSavings":1,"topChannels":[],"topQueries":[{"value":"test","count":1}]"#));
+        assert!(body.contains(r#""quotaSpent":0,"requestsServed":0,"#));
+        assert_eq!(invalid.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn test_usage_is_saved_on_shutdown() {
+        //GIVEN client whose usage ledger has a file and youtube returning categories
+        let mut path = env::temp_dir();
+        path.push(format!("usage_shutdown_test_{}.json", std::process::id()));
+        let _categories = mock("GET", Matcher::Regex(r"/videoCategories\?.*".to_string())).with_body(load_test_file("categories_result.json")).create();
+        let usage = UsageLedger::new(Some(path.clone())).unwrap();
+        let youtube_manager = YoutubeManager::new(KeyManager::new_test(DEFAULT_KEYS.clone()), mockito::server_url(), &test_config(), None, None, usage).unwrap();
+        let client = Client::tracked(make_rocket(RocketConfig::debug_default(), Arc::new(ClientRegistry::new(vec![], None, KeyHasher::new(String::from("pepper")))), youtube_manager)).unwrap();
+        //WHEN a request is served and the server shuts down before the periodic save
+        client.get("/v1/meta/categories?region=gb").dispatch();
+        client.terminate();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        //THEN the usage was written
+        assert!(saved.contains(r#""requestsServed":1"#));
+    }
+
+    #[test]
+    fn test_single_video_not_found() {
+        let _categories = mock("GET", Matcher::Regex(r"/videoCategories\?.*".to_string())).with_body(load_test_file("categories_result.json")).expect(0).create();
+        run_resource_test("single_result_empty.json", r"/videos\?.*", || {
+            //GIVEN client with default keys
+            let client = make_client(DEFAULT_KEYS.clone(), None);
+            //WHEN requesting a video that doesn't exist
+            let response = client.get("/v1/video/missing").dispatch();
+            //THEN response is a json not found error
+            assert_eq!(response.status(), Status::NotFound);
+            assert!(response.into_string().unwrap().contains(r#""code":"not_found""#));
+        });
+        _categories.assert();
+    }
+
+    #[test]
+    fn test_quota_exceeded_expires_keys() {
+        //GIVEN client with default keys and youtube reporting the quota as exceeded
+        let body = r#"{"error":{"code":403,"message":"Quota exceeded","errors":[{"domain":"youtube.quota","reason":"quotaExceeded"}]}}"#;
+        let _mock = mock("GET", Matcher::Regex(r"/search\?.*".to_string())).with_status(403).with_body(body).expect(2).create();
+        let client = make_client(DEFAULT_KEYS.clone(), None);
+        //WHEN searching
+        let response = client.get("/v1/search/video?q=test").dispatch();
+        let status = client.get("/v1/admin/status").dispatch();
+        //THEN both keys are tried and marked as exhausted
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let status = status.into_string().unwrap();
+        assert!(status.contains(r#""0":{"label":"key0","quota":0,"#));
+        assert!(status.contains(r#""1":{"label":"key1","quota":0,"#));
+        _mock.assert();
+    }
+
+    #[test]
+    fn test_failed_requests_are_refunded_to_the_client() {
+        //GIVEN client with a budget of one search and youtube failing
+        let entry = ClientEntry { name: String::from("app"), key: Some(String::from("secret")), key_hash: String::new(), scopes: Scope::all(), daily_budget: Some(100), rate_limit: None };
+        let _mock = mock("GET", Matcher::Regex(r"/search\?.*".to_string())).with_status(500).expect(4).create();
+        let client = make_client_with_clients(DEFAULT_KEYS.clone(), vec![entry]);
+        //WHEN searching, which is retried until it fails
+        let response = client.get("/v1/search/video?q=test").header(Header::new("x-api-key", "secret")).dispatch();
+        let clients = client.get("/v1/admin/clients").header(Header::new("x-api-key", "secret")).dispatch();
+        //THEN the client isn't charged for the retries or the failure
+        assert_eq!(response.status(), Status::BadGateway);
+        assert!(clients.into_string().unwrap().contains(r#""spent":0,"#));
+        _mock.assert();
+    }
+
+    #[test]
+    fn test_server_errors_are_retried_with_new_keys() {
+        //GIVEN client with default keys and youtube failing
+        let _mock = mock("GET", Matcher::Regex(r"/search\?.*".to_string())).with_status(500).expect(4).create();
+        let client = make_client(DEFAULT_KEYS.clone(), None);
+        //WHEN searching
+        let response = client.get("/v1/search/video?q=test").dispatch();
+        let diagnostics = client.get("/v1/admin/diagnostics").dispatch();
+        //THEN request is retried up to the limit and the attempts are recorded
+        assert_eq!(response.status(), Status::BadGateway);
+        let diagnostics = diagnostics.into_string().unwrap();
+        assert_eq!(diagnostics.matches(r#""outcome":"server_error""#).count(), 4);
+        _mock.assert();
+    }
+
+    #[test]
+    fn test_invalid_key_is_disabled_until_enabled() {
+        //GIVEN client with one key and youtube reporting the key as invalid
+        let body = r#"{"error":{"code":400,"message":"API key not valid","errors":[{"domain":"usageLimits","reason":"keyInvalid"}]}}"#;
+        let _mock = mock("GET", Matcher::Regex(r"/search\?.*".to_string())).with_status(400).with_body(body).create();
+        let client = make_client(vec!["key1"], None);
+        //WHEN searching and then enabling the key
+        let response = client.get("/v1/search/video?q=test").dispatch();
+        let disabled_status = client.get("/v1/admin/status").dispatch();
+        let enable = client.post("/v1/admin/keys/0/enable").dispatch();
+        let missing = client.post("/v1/admin/keys/5/enable").dispatch();
+        let enabled_status = client.get("/v1/admin/status").dispatch();
+        //THEN key is disabled with the reason, and enabled again afterwards
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        assert!(disabled_status.into_string().unwrap().contains(r#""disabled":true,"disabledReason":"keyInvalid""#));
+        assert_eq!(enable.status(), Status::Ok);
+        assert_eq!(missing.status(), Status::NotFound);
+        assert!(enabled_status.into_string().unwrap().contains(r#""disabled":false"#));
+    }
+
+    #[test]
+    fn test_key_pool_admin() {
+        //GIVEN client with one key
+        let client = make_client(vec!["key1"], None);
+        //WHEN a key is added, relabelled and disabled and the first key is removed
+        let added = client.post("/v1/admin/keys").header(ContentType::JSON).body(r#"{"key":"key2","label":"spare"}"#).dispatch();
+        let duplicate = client.post("/v1/admin/keys").header(ContentType::JSON).body(r#"{"key":"key2","label":"again"}"#).dispatch();
+        let relabelled = client.put("/v1/admin/keys/1/label").header(ContentType::JSON).body(r#"{"label":"backup"}"#).dispatch();
+        let disabled = client.post("/v1/admin/keys/1/disable").dispatch();
+        let removed = client.delete("/v1/admin/keys/0").dispatch();
+        let status = client.get("/v1/admin/status").dispatch();
+        //THEN only the new key remains with the new label
+        assert_eq!(added.status(), Status::Created);
+        assert_eq!(duplicate.status(), Status::BadRequest);
+        assert_eq!(relabelled.status(), Status::Ok);
+        assert_eq!(disabled.status(), Status::Ok);
+        assert_eq!(removed.status(), Status::Ok);
+        assert_eq!(status.into_string(), Some(r#"{"0":{"label":"backup","quota":10000,"disabled":true,"disabledReason":"Disabled by admin"}}"#.into()));
+    }
+
+    #[test]
+    fn test_key_changes_are_saved() {
+        //GIVEN client with one key and a key file
+        let mut path = env::temp_dir();
+        path.push(format!("youtube_keys_admin_test_{}.json", std::process::id()));
+        let youtube_manager = YoutubeManager::new(KeyManager::new_test(vec!["key1"]), mockito::server_url(), &test_config(), Some(KeyStore::new(path.clone())), None, UsageLedger::new(None).unwrap()).unwrap();
+        let client = Client::tracked(make_rocket(RocketConfig::debug_default(), Arc::new(ClientRegistry::new(vec![], None, KeyHasher::new(String::from("pepper")))), youtube_manager)).unwrap();
+        //WHEN a key is added and the first is disabled
+        client.post("/v1/admin/keys").header(ContentType::JSON).body(r#"{"key":"key2","label":"spare"}"#).dispatch();
+        client.post("/v1/admin/keys/0/disable").dispatch();
+        let saved = KeyStore::new(path.clone()).load().unwrap();
+        std::fs::remove_file(path).unwrap();
+        //THEN the file has both changes
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[0].disabled_reason, Some(String::from("Disabled by admin")));
+        assert_eq!(saved[1].label, "spare");
+    }
+
     #[test]
     fn test_status_1_full_key() {
         //GIVEN client with one key
         let client = make_client(vec!["key1"], None);
         //WHEN making /v1/admin/status request
-        let mut response = client.get("/v1/admin/status").dispatch();
+        let response = client.get("/v1/admin/status").dispatch();
         //THEN response is as expected
         assert_eq!(response.status(), Status::Ok);
-        assert_eq!(response.body_string(), Some(r#"{"0":10000}"#.into()));
+        assert_eq!(response.into_string(), Some(r#"{"0":{"label":"key0","quota":10000,"disabled":false}}"#.into()));
     }
 
     #[test]
@@ -172,15 +815,106 @@ mod test {
             //GIVEN client with default keys
             let client = make_client(DEFAULT_KEYS.clone(), None);
             //WHEN search for channels by 'test'
-            let mut response = client.get("/v1/search/channel?q=test").dispatch();
+            let response = client.get("/v1/search/channel?q=test").dispatch();
             //THEN check results are list of channels
             let expected = load_test_file("search_expected_channel.json");
             assert_eq!(response.status().code, 200);
-            assert_eq!(response.body_string(), Some(expected));
+            assert_eq!(response.into_string(), Some(expected));
         });
     }
 
-    fn run_resource_test(file: &'static str, path: &'static str, test: impl Fn() -> ()) {
+    #[test]
+    fn test_categories_are_cached() {
+        //GIVEN client with default keys and youtube returning categories once
+        let json = load_test_file("categories_result.json");
+        let _mock = mock("GET", Matcher::Regex(r"/videoCategories\?.*".to_string())).with_body(json).expect(1).create();
+        let client = make_client(DEFAULT_KEYS.clone(), None);
+        //WHEN requesting categories twice
+        let first = client.get("/v1/meta/categories?region=gb").dispatch();
+        let second = client.get("/v1/meta/categories?region=GB").dispatch();
+        //THEN both responses match and youtube was only called once
+        assert_eq!(first.status(), Status::Ok);
+        assert_eq!(second.status(), Status::Ok);
+        let expected = Some(String::from(r#"[{"id":"1","title":"Film & Animation","assignable":true},{"id":"10","title":"Music","assignable":true}]"#));
+        assert_eq!(first.into_string(), expected);
+        assert_eq!(second.into_string(), expected);
+        _mock.assert();
+    }
+
+    #[test]
+    fn test_purged_caches_are_refetched() {
+        //GIVEN client with cached categories
+        let json = load_test_file("categories_result.json");
+        let _mock = mock("GET", Matcher::Regex(r"/videoCategories\?.*".to_string())).with_body(json).expect(2).create();
+        let client = make_client(DEFAULT_KEYS.clone(), TEST_API_KEY.clone());
+        let first = client.get("/v1/meta/categories?region=us").header(Header::new("x-api-key", "test")).dispatch();
+        //WHEN purging the caches and requesting categories again
+        let unauthorized = client.post("/v1/admin/cache/purge").dispatch();
+        let purge = client.post("/v1/admin/cache/purge").header(Header::new("x-api-key", "test")).dispatch();
+        let second = client.get("/v1/meta/categories?region=us").header(Header::new("x-api-key", "test")).dispatch();
+        //THEN purging needs the admin key and youtube is called again
+        assert_eq!(unauthorized.status(), Status::Unauthorized);
+        assert_eq!(purge.status(), Status::Ok);
+        assert_eq!(first.status(), Status::Ok);
+        assert_eq!(second.status(), Status::Ok);
+        _mock.assert();
+    }
+
+    #[test]
+    fn test_playlist_budget_returns_partial_result() {
+        //GIVEN youtube returning a playlist in two pages
+        let _page1 = mock("GET", Matcher::Regex(r"/playlistItems\?.*playlistId=PL1&key=".to_string())).with_body(load_test_file("playlist_items_page1.json")).expect(2).create();
+        let _page2 = mock("GET", Matcher::Regex(r"/playlistItems\?.*pageToken=CAIQAA".to_string())).with_body(load_test_file("playlist_items_page2.json")).expect(2).create();
+        let client = make_client(DEFAULT_KEYS.clone(), None);
+        //WHEN requesting it with a budget for one page, then continuing, then without a budget
+        let partial = client.get("/v1/playlist/PL1/videos?budget_quota=1").dispatch();
+        let rest = client.get("/v1/playlist/PL1/videos?cursor=CAIQAA").header(Header::new("x-budget-quota", "1")).dispatch();
+        let all = client.get("/v1/playlist/PL1/videos").dispatch();
+        let invalid = client.get("/v1/playlist/PL1/videos?budget_ms=soon").dispatch();
+        //THEN the partial result has the cursor to continue from
+        let count = |body: Option<String>| serde_json::from_str::<Vec<serde_json::Value>>(&body.unwrap()).unwrap().len();
+        assert_eq!(partial.status(), Status::Ok);
+        assert_eq!(partial.headers().get_one(budget::CURSOR_HEADER), Some("CAIQAA"));
+        assert_eq!(count(partial.into_string()), 2);
+        assert_eq!(rest.headers().get_one(budget::CURSOR_HEADER), None);
+        assert_eq!(count(rest.into_string()), 1);
+        assert_eq!(all.headers().get_one(budget::CURSOR_HEADER), None);
+        assert_eq!(count(all.into_string()), 3);
+        assert_eq!(invalid.status(), Status::BadRequest);
+        _page1.assert();
+        _page2.assert();
+    }
+
+    #[test]
+    fn test_time_budget_cuts_off_slow_calls() {
+        //GIVEN youtube answering the second page of one playlist and the first of another slowly
+        let slow_body = |file: &'static str| {
+            let json = load_test_file(file);
+            move |writer: &mut dyn std::io::Write| {
+                std::thread::sleep(std::time::Duration::from_secs(2));
+                writer.write_all(json.as_bytes())
+            }
+        };
+        let _page1 = mock("GET", Matcher::Regex(r"/playlistItems\?.*playlistId=PL2&key=".to_string())).with_body(load_test_file("playlist_items_page1.json")).create();
+        let _page2 = mock("GET", Matcher::Regex(r"/playlistItems\?.*playlistId=PL2&pageToken=CAIQAA".to_string())).with_body_from_fn(slow_body("playlist_items_page2.json")).create();
+        let _slow = mock("GET", Matcher::Regex(r"/playlistItems\?.*playlistId=PL3&key=".to_string())).with_body_from_fn(slow_body("playlist_items_page1.json")).create();
+        let client = make_client(DEFAULT_KEYS.clone(), None);
+        //WHEN requesting them with a time budget shorter than the slow call
+        let started = std::time::Instant::now();
+        let partial = client.get("/v1/playlist/PL2/videos?budget_ms=500").dispatch();
+        let exhausted = client.get("/v1/playlist/PL3/videos?budget_ms=500").dispatch();
+        let elapsed = started.elapsed();
+        let usage = client.get("/v1/admin/usage").dispatch().into_string().unwrap();
+        //THEN each returns when its budget runs out instead of waiting for youtube, and only the answered call is counted as spend
+        assert_eq!(partial.status(), Status::Ok);
+        assert_eq!(partial.headers().get_one(budget::CURSOR_HEADER), Some("CAIQAA"));
+        assert_eq!(exhausted.status(), Status::TooManyRequests);
+        assert!(exhausted.into_string().unwrap().contains(r#""code":"budget_exhausted""#));
+        assert!(elapsed < std::time::Duration::from_millis(1500), "took {:?}", elapsed);
+        assert!(usage.contains(r#""quotaSpent":1,"#), "{}", usage);
+    }
+
+    fn run_resource_test(file: &'static str, path: &'static str, test: impl Fn()) {
         let json = load_test_file(file);
         let _mock = mock("GET", Matcher::Regex(path.to_string())).with_body(json.clone()).create();
         test();
@@ -190,6 +924,6 @@ mod test {
     fn load_test_file(file: &'static str) -> String {
         let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         file_path.push(format!("test/resources/{}", file));
-        return std::fs::read_to_string(file_path).unwrap();
+        std::fs::read_to_string(file_path).unwrap()
     }
 }
\ No newline at end of file
@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use lazy_static::lazy_static;
+use rocket::{Data, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+
+/// Upper bounds in seconds, shared by all latency histograms
+const BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0];
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::new();
+}
+
+struct Histogram {
+    counts: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn new() -> Histogram {
+        Histogram {
+            counts: [0; BUCKETS.len()],
+            count: 0,
+            sum: 0.0,
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (i, bound) in BUCKETS.iter().enumerate() {
+            if seconds <= *bound {
+                self.counts[i] += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        for (i, bound) in BUCKETS.iter().enumerate() {
+            let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, self.counts[i]);
+        }
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, self.count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+struct Series {
+    /// (route, status)
+    requests: BTreeMap<(String, u16), Histogram>,
+    /// (resource, status or outcome)
+    upstream: BTreeMap<(String, String), Histogram>,
+    /// resource
+    retries: BTreeMap<String, u64>,
+    /// (cache, hit)
+    cache: BTreeMap<(&'static str, bool), u64>,
+}
+
+/// Process wide counters, rendered by `/metrics` in the Prometheus text format
+pub struct Metrics {
+    series: Mutex<Series>,
+}
+
+impl Metrics {
+    fn new() -> Metrics {
+        Metrics {
+            series: Mutex::new(Series {
+                requests: BTreeMap::new(),
+                upstream: BTreeMap::new(),
+                retries: BTreeMap::new(),
+                cache: BTreeMap::new(),
+            }),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn record_request(&self, route: &str, status: u16, elapsed: Duration) {
+        let mut series = self.series.lock().unwrap();
+        series.requests.entry((route.to_string(), status)).or_insert_with(Histogram::new).observe(elapsed);
+    }
+
+    /// `result` is the HTTP status from YouTube, or the error if there wasn't one
+    pub fn record_upstream(&self, resource: &str, result: String, elapsed: Duration) {
+        let mut series = self.series.lock().unwrap();
+        series.upstream.entry((resource.to_string(), result)).or_insert_with(Histogram::new).observe(elapsed);
+    }
+
+    pub fn record_retries(&self, resource: &str, retries: usize) {
+        if retries > 0 {
+            *self.series.lock().unwrap().retries.entry(resource.to_string()).or_insert(0) += retries as u64;
+        }
+    }
+
+    pub fn record_cache(&self, cache: &'static str, hit: bool) {
+        *self.series.lock().unwrap().cache.entry((cache, hit)).or_insert(0) += 1;
+    }
+
+    /// `keys` is (label, remaining, spent) for each key, these come from the key manager at render time
+    pub fn render(&self, keys: &[(String, usize, usize)]) -> String {
+        let series = self.series.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP http_request_duration_seconds Time to handle requests by route and status\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for ((route, status), histogram) in &series.requests {
+            histogram.render(&mut out, "http_request_duration_seconds", &format!("route=\"{}\",status=\"{}\"", escape(route), status));
+        }
+
+        out.push_str("# HELP youtube_request_duration_seconds Time of each call to YouTube by resource and status\n");
+        out.push_str("# TYPE youtube_request_duration_seconds histogram\n");
+        for ((resource, result), histogram) in &series.upstream {
+            histogram.render(&mut out, "youtube_request_duration_seconds", &format!("resource=\"{}\",status=\"{}\"", escape(resource), result));
+        }
+
+        out.push_str("# HELP youtube_retries_total Calls to YouTube that were retries of an earlier failed call\n");
+        out.push_str("# TYPE youtube_retries_total counter\n");
+        for (resource, count) in &series.retries {
+            let _ = writeln!(out, "youtube_retries_total{{resource=\"{}\"}} {}", escape(resource), count);
+        }
+
+        out.push_str("# HELP cache_requests_total Cache lookups by cache and result\n");
+        out.push_str("# TYPE cache_requests_total counter\n");
+        for ((cache, hit), count) in &series.cache {
+            let _ = writeln!(out, "cache_requests_total{{cache=\"{}\",result=\"{}\"}} {}", cache, if *hit { "hit" } else { "miss" }, count);
+        }
+
+        out.push_str("# HELP youtube_key_quota_remaining Estimated quota left until the next reset\n");
+        out.push_str("# TYPE youtube_key_quota_remaining gauge\n");
+        for (label, remaining, _) in keys {
+            let _ = writeln!(out, "youtube_key_quota_remaining{{key=\"{}\"}} {}", escape(label), remaining);
+        }
+        out.push_str("# HELP youtube_key_quota_spent Estimated quota spent since the last reset\n");
+        out.push_str("# TYPE youtube_key_quota_spent gauge\n");
+        for (label, _, spent) in keys {
+            let _ = writeln!(out, "youtube_key_quota_spent{{key=\"{}\"}} {}", escape(label), spent);
+        }
+
+        out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+struct RequestStart(Instant);
+
+/// Times every request and records it against the matched route, or `unmatched` for 404s
+pub struct MetricsFairing {}
+
+#[rocket::async_trait]
+impl Fairing for MetricsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let started = request.local_cache(|| RequestStart(Instant::now()));
+        let route = request.route()
+            .map(|route| route.uri.path().to_string())
+            .unwrap_or(String::from("unmatched"));
+        METRICS.record_request(&route, response.status().code, started.0.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        //GIVEN metrics with one of each series
+        let metrics = Metrics::new();
+        metrics.record_request("/v1/video/<id>", 200, Duration::from_millis(20));
+        metrics.record_upstream("videos", String::from("403"), Duration::from_millis(300));
+        metrics.record_retries("videos", 2);
+        metrics.record_cache("categories", true);
+        //WHEN rendered
+        let text = metrics.render(&[(String::from("main"), 9000, 1000)]);
+        //THEN all series are in the prometheus format
+        assert!(text.contains("http_request_duration_seconds_bucket{route=\"/v1/video/<id>\",status=\"200\",le=\"0.025\"} 1\n"));
+        assert!(text.contains("http_request_duration_seconds_bucket{route=\"/v1/video/<id>\",status=\"200\",le=\"0.01\"} 0\n"));
+        assert!(text.contains("youtube_request_duration_seconds_count{resource=\"videos\",status=\"403\"} 1\n"));
+        assert!(text.contains("youtube_retries_total{resource=\"videos\"} 2\n"));
+        assert!(text.contains("cache_requests_total{cache=\"categories\",result=\"hit\"} 1\n"));
+        assert!(text.contains("youtube_key_quota_remaining{key=\"main\"} 9000\n"));
+        assert!(text.contains("youtube_key_quota_spent{key=\"main\"} 1000\n"));
+    }
+}
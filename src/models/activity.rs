@@ -0,0 +1,40 @@
+use serde::Serialize;
+use crate::models::video::Video;
+use crate::models::playlist::Playlist;
+use crate::models::channel::Channel;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Activity {
+    id: String,
+    #[serde(rename = "type")]
+    activity_type: String,
+    date: String,
+    channel_id: String,
+    channel_title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    video: Option<Video>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    playlist: Option<Playlist>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel: Option<Channel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    playlist_id: Option<String>,
+}
+
+impl Activity {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(id: String, activity_type: String, date: String, channel_id: String, channel_title: String, video: Option<Video>, playlist: Option<Playlist>, channel: Option<Channel>, playlist_id: Option<String>) -> Self {
+        Activity {
+            id,
+            activity_type,
+            date,
+            channel_id,
+            channel_title,
+            video,
+            playlist,
+            channel,
+            playlist_id,
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Category {
+    id: String,
+    title: String,
+    assignable: bool,
+}
+
+impl Category {
+    pub fn new(id: String, title: String, assignable: bool) -> Self {
+        Category { id, title, assignable }
+    }
+}
+
+impl Category {
+    pub fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn get_title(&self) -> String {
+        self.title.clone()
+    }
+}
@@ -0,0 +1,33 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    id: String,
+    #[serde(rename = "type")]
+    message_type: String,
+    date: String,
+    message: Option<String>,
+    author_channel_id: String,
+    author_name: String,
+    author_thumbnail: Option<String>,
+    is_owner: bool,
+    is_moderator: bool,
+}
+
+impl ChatMessage {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(id: String, message_type: String, date: String, message: Option<String>, author_channel_id: String, author_name: String, author_thumbnail: Option<String>, is_owner: bool, is_moderator: bool) -> Self {
+        ChatMessage {
+            id,
+            message_type,
+            date,
+            message,
+            author_channel_id,
+            author_name,
+            author_thumbnail,
+            is_owner,
+            is_moderator,
+        }
+    }
+}
@@ -1,29 +1,16 @@
-use anyhow::{Result, Error};
-use rocket::request::{FromParam};
-use rocket::http::RawStr;
+use rocket::request::FromParam;
 
+#[allow(clippy::upper_case_acronyms)]
 pub enum ContentType {
     CHANNEL,
     VIDEO,
     PLAYLIST
 }
 
-impl ContentType {
-    pub fn from_string(value: &str) -> Result<ContentType> {
-        match value {
-            "channel" => Ok(ContentType::CHANNEL),
-            "video" => Ok(ContentType::VIDEO),
-            "playlist" => Ok(ContentType::PLAYLIST),
-            _ => Err(Error::msg(format!("Invalid type: {}", value)))
-        }
-    }
-}
-
 impl <'v> FromParam<'v> for ContentType {
     type Error = String;
 
-    fn from_param(param: &'v RawStr) -> Result<Self, Self::Error> {
-        let value = param.as_str();
+    fn from_param(value: &'v str) -> Result<Self, Self::Error> {
         match value {
             "channel" => Ok(ContentType::CHANNEL),
             "video" => Ok(ContentType::VIDEO),
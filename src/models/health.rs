@@ -0,0 +1,44 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Degraded,
+    Unhealthy,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HealthCheck {
+    status: HealthStatus,
+    message: String,
+}
+
+impl HealthCheck {
+    pub fn new(status: HealthStatus, message: String) -> Self {
+        HealthCheck { status, message }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HealthReport {
+    /// Worst status of all the checks
+    status: HealthStatus,
+    checks: BTreeMap<&'static str, HealthCheck>,
+}
+
+impl HealthReport {
+    pub fn new(checks: BTreeMap<&'static str, HealthCheck>) -> Self {
+        let status = checks.values()
+            .map(|check| check.status)
+            .fold(HealthStatus::Ok, |worst, status| if status > worst { status } else { worst });
+        HealthReport { status, checks }
+    }
+}
+
+impl HealthReport {
+    pub fn get_status(&self) -> HealthStatus {
+        self.status
+    }
+}
@@ -0,0 +1,33 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyStatus {
+    label: String,
+    quota: usize,
+    disabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disabled_reason: Option<String>,
+}
+
+impl KeyStatus {
+    pub fn new(label: String, quota: usize, disabled_reason: Option<String>) -> Self {
+        KeyStatus {
+            label,
+            quota,
+            disabled: disabled_reason.is_some(),
+            disabled_reason,
+        }
+    }
+}
+
+#[cfg(test)]
+impl KeyStatus {
+    pub fn get_quota(&self) -> usize {
+        self.quota
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+}
@@ -0,0 +1,28 @@
+use serde::Serialize;
+
+/// Result of a cheap call made with a single key
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyTest {
+    label: String,
+    /// `ok`, `exhausted`, `invalid`, `error` or `request_error`
+    outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    reasons: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disabled_reason: Option<String>,
+}
+
+impl KeyTest {
+    pub fn new(label: String, outcome: &'static str, status: Option<u16>, reasons: Vec<String>, disabled_reason: Option<String>) -> Self {
+        KeyTest { label, outcome, status, reasons, disabled_reason }
+    }
+}
+
+impl KeyTest {
+    pub fn is_ok(&self) -> bool {
+        self.outcome == "ok"
+    }
+}
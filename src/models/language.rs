@@ -0,0 +1,14 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Language {
+    id: String,
+    name: String,
+}
+
+impl Language {
+    pub fn new(id: String, name: String) -> Self {
+        Language { id, name }
+    }
+}
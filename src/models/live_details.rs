@@ -0,0 +1,31 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LiveDetails {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scheduled_start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actual_start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    concurrent_viewers: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    active_chat_id: Option<String>,
+}
+
+impl LiveDetails {
+    pub fn new(scheduled_start: Option<String>, actual_start: Option<String>, concurrent_viewers: Option<u64>, active_chat_id: Option<String>) -> Self {
+        LiveDetails {
+            scheduled_start,
+            actual_start,
+            concurrent_viewers,
+            active_chat_id,
+        }
+    }
+}
+
+impl LiveDetails {
+    pub fn get_active_chat_id(&self) -> Option<String> {
+        self.active_chat_id.clone()
+    }
+}
@@ -3,4 +3,17 @@ pub mod channel;
 pub mod video;
 pub mod playlist;
 pub mod youtube;
-pub mod channel_stats;
\ No newline at end of file
+pub mod category;
+pub mod region;
+pub mod language;
+pub mod activity;
+pub mod live_details;
+pub mod chat_message;
+pub mod video_stats;
+pub mod page;
+pub mod key_status;
+pub mod reconciliation;
+pub mod health;
+pub mod spend;
+pub mod usage;
+pub mod key_test;
@@ -0,0 +1,61 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Reconciliation {
+    enabled: bool,
+    keys: Vec<KeyReconciliation>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyReconciliation {
+    label: String,
+    estimated_spend: usize,
+    exhaustions: usize,
+    average_spend_at_exhaustion: Option<usize>,
+    /// Average estimated spend when YouTube reported the key as exhausted divided by the quota
+    /// Below 1 means costs are underestimated, above 1 means they are overestimated
+    estimate_ratio: Option<f64>,
+}
+
+impl Reconciliation {
+    pub fn new(enabled: bool, keys: Vec<KeyReconciliation>) -> Self {
+        Reconciliation { enabled, keys }
+    }
+}
+
+#[cfg(test)]
+impl Reconciliation {
+    pub fn get_keys(&self) -> &[KeyReconciliation] {
+        &self.keys
+    }
+}
+
+impl KeyReconciliation {
+    pub fn new(label: String, quota: usize, estimated_spend: usize, samples: &[usize]) -> Self {
+        let average = if samples.is_empty() {
+            None
+        } else {
+            Some(samples.iter().sum::<usize>() / samples.len())
+        };
+        KeyReconciliation {
+            label,
+            estimated_spend,
+            exhaustions: samples.len(),
+            average_spend_at_exhaustion: average,
+            estimate_ratio: average.map(|average| average as f64 / quota as f64),
+        }
+    }
+}
+
+#[cfg(test)]
+impl KeyReconciliation {
+    pub fn get_exhaustions(&self) -> usize {
+        self.exhaustions
+    }
+
+    pub fn get_average_spend_at_exhaustion(&self) -> Option<usize> {
+        self.average_spend_at_exhaustion
+    }
+}
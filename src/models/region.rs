@@ -0,0 +1,14 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    id: String,
+    name: String,
+}
+
+impl Region {
+    pub fn new(id: String, name: String) -> Self {
+        Region { id, name }
+    }
+}
@@ -0,0 +1,88 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SpendReport {
+    from: String,
+    to: String,
+    cost: usize,
+    calls: usize,
+    periods: Vec<SpendPeriod>,
+}
+
+/// Estimated quota spent during a day or month, broken down by who and what it was spent on
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SpendPeriod {
+    period: String,
+    cost: usize,
+    calls: usize,
+    by_client: BTreeMap<String, usize>,
+    by_route: BTreeMap<String, usize>,
+    by_resource: BTreeMap<String, usize>,
+    by_key: BTreeMap<String, usize>,
+}
+
+impl SpendReport {
+    pub fn new(from: String, to: String, periods: Vec<SpendPeriod>) -> Self {
+        SpendReport {
+            from,
+            to,
+            cost: periods.iter().map(|period| period.cost).sum(),
+            calls: periods.iter().map(|period| period.calls).sum(),
+            periods,
+        }
+    }
+}
+
+#[cfg(test)]
+impl SpendReport {
+    pub fn get_cost(&self) -> usize {
+        self.cost
+    }
+
+    pub fn get_periods(&self) -> &[SpendPeriod] {
+        &self.periods
+    }
+}
+
+impl SpendPeriod {
+    pub fn new(period: String) -> Self {
+        SpendPeriod {
+            period,
+            cost: 0,
+            calls: 0,
+            by_client: BTreeMap::new(),
+            by_route: BTreeMap::new(),
+            by_resource: BTreeMap::new(),
+            by_key: BTreeMap::new(),
+        }
+    }
+}
+
+impl SpendPeriod {
+    pub fn add(&mut self, client: &str, route: &str, resource: &str, key: &str, cost: usize) {
+        self.cost += cost;
+        self.calls += 1;
+        *self.by_client.entry(client.to_string()).or_insert(0) += cost;
+        *self.by_route.entry(route.to_string()).or_insert(0) += cost;
+        *self.by_resource.entry(resource.to_string()).or_insert(0) += cost;
+        *self.by_key.entry(key.to_string()).or_insert(0) += cost;
+    }
+}
+
+#[cfg(test)]
+impl SpendPeriod {
+    pub fn get_period(&self) -> &str {
+        &self.period
+    }
+
+    pub fn get_cost(&self) -> usize {
+        self.cost
+    }
+
+    pub fn get_by_client(&self) -> &BTreeMap<String, usize> {
+        &self.by_client
+    }
+}
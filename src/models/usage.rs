@@ -0,0 +1,58 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReport {
+    month: String,
+    from: String,
+    to: String,
+    quota_spent: usize,
+    requests_served: usize,
+    cache_hits: usize,
+    /// Estimated quota the cache hits would have cost
+    cache_savings: usize,
+    top_channels: Vec<Ranking>,
+    top_queries: Vec<Ranking>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Ranking {
+    value: String,
+    count: usize,
+}
+
+impl UsageReport {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(month: String, from: String, to: String, quota_spent: usize, requests_served: usize, cache_hits: usize, cache_savings: usize, top_channels: Vec<Ranking>, top_queries: Vec<Ranking>) -> Self {
+        UsageReport { month, from, to, quota_spent, requests_served, cache_hits, cache_savings, top_channels, top_queries }
+    }
+}
+
+#[cfg(test)]
+impl UsageReport {
+    pub fn get_month(&self) -> &str {
+        &self.month
+    }
+
+    pub fn get_quota_spent(&self) -> usize {
+        self.quota_spent
+    }
+
+    pub fn get_requests_served(&self) -> usize {
+        self.requests_served
+    }
+
+    pub fn get_cache_savings(&self) -> usize {
+        self.cache_savings
+    }
+
+    pub fn get_top_queries(&self) -> &[Ranking] {
+        &self.top_queries
+    }
+}
+
+impl Ranking {
+    pub fn new(value: String, count: usize) -> Self {
+        Ranking { value, count }
+    }
+}
@@ -1,6 +1,8 @@
 use serde::Serialize;
+use crate::models::live_details::LiveDetails;
+use crate::models::video_stats::VideoStats;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Video {
     id: String,
@@ -10,10 +12,19 @@ pub struct Video {
     channel_id: String,
     channel_title: String,
     description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category_title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    live: Option<LiveDetails>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    statistics: Option<VideoStats>,
 }
 
 impl Video {
-    pub fn new(id: String, title: String, date: String, thumbnail: String, channel_id: String, channel_title: String, description: Option<String>) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(id: String, title: String, date: String, thumbnail: String, channel_id: String, channel_title: String, description: Option<String>, category_id: Option<String>) -> Self {
         Video {
             id,
             title,
@@ -22,6 +33,32 @@ impl Video {
             channel_id,
             channel_title,
             description,
+            category_id,
+            category_title: None,
+            live: None,
+            statistics: None,
         }
     }
-}
\ No newline at end of file
+}
+
+impl Video {
+    pub fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn get_category_id(&self) -> Option<String> {
+        self.category_id.clone()
+    }
+
+    pub fn set_category_title(&mut self, title: String) {
+        self.category_title = Some(title);
+    }
+
+    pub fn set_live_details(&mut self, live: LiveDetails) {
+        self.live = Some(live);
+    }
+
+    pub fn set_statistics(&mut self, statistics: VideoStats) {
+        self.statistics = Some(statistics);
+    }
+}
@@ -0,0 +1,20 @@
+use serde::Serialize;
+use crate::models::youtube::parts::stats::Stats;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoStats {
+    views: Option<u64>,
+    likes: Option<u64>,
+    comments: Option<u64>,
+}
+
+impl From<Stats> for VideoStats {
+    fn from(stats: Stats) -> Self {
+        VideoStats {
+            views: stats.get_view_count(),
+            likes: stats.get_like_count(),
+            comments: stats.get_comment_count(),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorResponse {
+    error: ErrorEnvelope,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ErrorEnvelope {
+    message: Option<String>,
+    #[serde(default)]
+    errors: Vec<ErrorReason>,
+    #[serde(default)]
+    details: Vec<ErrorReason>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ErrorReason {
+    reason: Option<String>,
+}
+
+impl ErrorResponse {
+    pub fn get_message(&self) -> Option<String> {
+        self.error.message.clone()
+    }
+
+    /// Returns the reasons from both the v3 `errors` list and the newer `details` list
+    pub fn get_reasons(&self) -> Vec<String> {
+        self.error.errors.iter()
+            .chain(self.error.details.iter())
+            .filter_map(|item| item.reason.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,46 @@
+use crate::models::youtube::parts::snippet::Snippet;
+use crate::models::youtube::parts::activity_details::ActivityDetails;
+use crate::models::youtube::parts::thumbnails::Thumbnails;
+use crate::models::activity::Activity;
+use crate::models::video::Video;
+use crate::models::playlist::Playlist;
+use crate::models::channel::Channel;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityItem {
+    id: String,
+    snippet: Snippet,
+    content_details: Option<ActivityDetails>,
+}
+
+impl ActivityItem {
+    pub fn into_activity(self) -> Activity {
+        let details = self.content_details.unwrap_or_default();
+        let snippet = self.snippet;
+        let activity_type = snippet.activity_type.unwrap_or(String::from("unknown"));
+        let date = snippet.published_at.unwrap_or_default();
+        let channel_id = snippet.channel_id.unwrap_or_default();
+        let channel_title = snippet.channel_title.unwrap_or_default();
+        let thumbnail = snippet.thumbnails.unwrap_or(Thumbnails::empty()).get_url();
+
+        let mut video = None;
+        let mut playlist = None;
+        let mut channel = None;
+
+        if let Some(video_id) = details.get_upload_video_id() {
+            video = Some(Video::new(video_id, snippet.title, date.clone(), thumbnail, channel_id.clone(), channel_title.clone(), snippet.description, None));
+        } else if let Some(resource) = details.get_resource() {
+            if resource.is_video() {
+                video = Some(Video::new(resource.get_id(), snippet.title, date.clone(), thumbnail, channel_id.clone(), channel_title.clone(), snippet.description, None));
+            } else if resource.is_playlist() {
+                playlist = Some(Playlist::new(resource.get_id(), snippet.title, thumbnail, channel_id.clone(), channel_title.clone()));
+            } else if resource.is_channel() {
+                channel = Some(Channel::new(resource.get_id(), snippet.title, thumbnail, None, None));
+            }
+        }
+
+        Activity::new(self.id, activity_type, date, channel_id, channel_title, video, playlist, channel, details.get_playlist_id())
+    }
+}
@@ -0,0 +1,23 @@
+use serde::Deserialize;
+use crate::models::category::Category;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryItem {
+    id: String,
+    snippet: CategorySnippet,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CategorySnippet {
+    title: String,
+    #[serde(default)]
+    assignable: bool,
+}
+
+impl CategoryItem {
+    pub fn into_category(self) -> Category {
+        Category::new(self.id, self.snippet.title, self.snippet.assignable)
+    }
+}
@@ -0,0 +1,47 @@
+use serde::Deserialize;
+use crate::models::chat_message::ChatMessage;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatItem {
+    id: String,
+    snippet: ChatSnippet,
+    author_details: AuthorDetails,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ChatSnippet {
+    #[serde(rename = "type")]
+    message_type: String,
+    published_at: String,
+    display_message: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AuthorDetails {
+    channel_id: String,
+    display_name: String,
+    profile_image_url: Option<String>,
+    #[serde(default)]
+    is_chat_owner: bool,
+    #[serde(default)]
+    is_chat_moderator: bool,
+}
+
+impl ChatItem {
+    pub fn into_chat_message(self) -> ChatMessage {
+        ChatMessage::new(
+            self.id,
+            self.snippet.message_type,
+            self.snippet.published_at,
+            self.snippet.display_message,
+            self.author_details.channel_id,
+            self.author_details.display_name,
+            self.author_details.profile_image_url,
+            self.author_details.is_chat_owner,
+            self.author_details.is_chat_moderator,
+        )
+    }
+}
@@ -0,0 +1,26 @@
+use serde::Deserialize;
+use crate::models::region::Region;
+use crate::models::language::Language;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct I18nItem {
+    id: String,
+    snippet: I18nSnippet,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct I18nSnippet {
+    name: String,
+}
+
+impl I18nItem {
+    pub fn into_region(self) -> Region {
+        Region::new(self.id, self.snippet.name)
+    }
+
+    pub fn into_language(self) -> Language {
+        Language::new(self.id, self.snippet.name)
+    }
+}
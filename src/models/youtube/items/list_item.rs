@@ -3,6 +3,7 @@ use crate::models::youtube::parts::stats::Stats;
 use crate::models::youtube::parts::content_details::ContentDetails;
 use crate::models::channel::Channel;
 use crate::models::video::Video;
+use crate::models::video_stats::VideoStats;
 use anyhow::{Result, Error};
 use serde::Deserialize;
 use crate::models::playlist::Playlist;
@@ -22,7 +23,7 @@ pub struct ListItem {
 impl ListItem {
     pub fn into_channel(self) -> Result<Channel> {
         if self.kind.is_channel() {
-            let stats = self.statistics.unwrap_or(Stats::default());
+            let stats = self.statistics.unwrap_or_default();
             Ok(Channel::new(
                 self.id,
                 self.snippet.title,
@@ -36,7 +37,7 @@ impl ListItem {
 
     pub fn into_video(self) -> Result<Video> {
         if self.kind.is_video() {
-            Ok(Video::new(
+            let mut video = Video::new(
                 self.id,
                 self.snippet.title,
                 self.snippet.published_at.unwrap(),
@@ -44,7 +45,12 @@ impl ListItem {
                 self.snippet.channel_id.unwrap(),
                 self.snippet.channel_title.unwrap(),
                 self.snippet.description,
-            ))
+                self.snippet.category_id,
+            );
+            if let Some(stats) = self.statistics {
+                video.set_statistics(VideoStats::from(stats));
+            }
+            Ok(video)
         } else {
             Err(Error::msg("Not a video"))
         }
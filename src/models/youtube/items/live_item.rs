@@ -0,0 +1,17 @@
+use serde::Deserialize;
+use crate::models::youtube::parts::live_streaming_details::LiveStreamingDetails;
+use crate::models::live_details::LiveDetails;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LiveItem {
+    id: String,
+    live_streaming_details: Option<LiveStreamingDetails>,
+}
+
+impl LiveItem {
+    pub fn into_live_details(self) -> Option<(String, LiveDetails)> {
+        let id = self.id;
+        self.live_streaming_details.map(|details| (id, details.into_live_details()))
+    }
+}
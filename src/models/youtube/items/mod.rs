@@ -1,3 +1,8 @@
 pub mod list_item;
 pub mod search_item;
-pub mod playlist_item;
\ No newline at end of file
+pub mod playlist_item;
+pub mod category_item;
+pub mod i18n_item;
+pub mod activity_item;
+pub mod live_item;
+pub mod chat_item;
\ No newline at end of file
@@ -22,6 +22,7 @@ impl PlaylistItem {
                 self.snippet.channel_id.unwrap(),
                 self.snippet.channel_title.unwrap(),
                 self.snippet.description,
+                self.snippet.category_id,
             ))
         } else {
             Err(Error::msg("Not a video (in playlist)"))
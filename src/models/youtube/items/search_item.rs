@@ -39,6 +39,7 @@ impl SearchItem {
                 self.snippet.channel_id.unwrap(),
                 self.snippet.channel_title.unwrap(),
                 self.snippet.description,
+                self.snippet.category_id,
             ))
         } else {
             Err(Error::msg("Not a video"))
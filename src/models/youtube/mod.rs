@@ -1,10 +1,19 @@
+// Mirrors YouTube's responses, not every field is read
+#![allow(dead_code)]
+
 use serde::Deserialize;
 use crate::models::youtube::items::list_item::ListItem;
 use crate::models::youtube::items::search_item::SearchItem;
 use crate::models::youtube::items::playlist_item::PlaylistItem;
+use crate::models::youtube::items::category_item::CategoryItem;
+use crate::models::youtube::items::i18n_item::I18nItem;
+use crate::models::youtube::items::activity_item::ActivityItem;
+use crate::models::youtube::items::live_item::LiveItem;
+use crate::models::youtube::items::chat_item::ChatItem;
 
 pub mod parts;
 pub mod items;
+pub mod error;
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -17,6 +26,7 @@ pub struct SearchResponse {
 pub struct ListResponse {
     pub items: Option<Vec<ListItem>>,
     pub page_info: PageInfo,
+    pub next_page_token: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -31,3 +41,36 @@ pub struct PlaylistResponse {
     pub items: Vec<PlaylistItem>,
     pub next_page_token: Option<String>,
 }
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryResponse {
+    pub items: Vec<CategoryItem>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct I18nResponse {
+    pub items: Vec<I18nItem>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityResponse {
+    pub items: Vec<ActivityItem>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LiveResponse {
+    pub items: Vec<LiveItem>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LiveChatResponse {
+    pub items: Vec<ChatItem>,
+    pub next_page_token: Option<String>,
+    pub polling_interval_millis: u64,
+    pub offline_at: Option<String>,
+}
@@ -0,0 +1,57 @@
+use serde::Deserialize;
+use crate::models::youtube::parts::id::Id;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityDetails {
+    upload: Option<Upload>,
+    like: Option<ResourceHolder>,
+    favorite: Option<ResourceHolder>,
+    comment: Option<ResourceHolder>,
+    subscription: Option<ResourceHolder>,
+    playlist_item: Option<PlaylistItemHolder>,
+    recommendation: Option<ResourceHolder>,
+    bulletin: Option<ResourceHolder>,
+    social: Option<ResourceHolder>,
+    channel_item: Option<ResourceHolder>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Upload {
+    video_id: String
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ResourceHolder {
+    resource_id: Option<Id>
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistItemHolder {
+    resource_id: Option<Id>,
+    playlist_id: String,
+}
+
+impl ActivityDetails {
+    pub fn get_upload_video_id(&self) -> Option<String> {
+        self.upload.as_ref().map(|upload| upload.video_id.clone())
+    }
+
+    pub fn get_playlist_id(&self) -> Option<String> {
+        self.playlist_item.as_ref().map(|item| item.playlist_id.clone())
+    }
+
+    /// Returns the resource (video, playlist or channel) the activity is about, uploads are handled by [get_upload_video_id]
+    pub fn get_resource(&self) -> Option<&Id> {
+        let holders = [&self.like, &self.favorite, &self.comment, &self.subscription,
+            &self.recommendation, &self.bulletin, &self.social, &self.channel_item];
+
+        holders.iter()
+            .filter_map(|holder| holder.as_ref().and_then(|holder| holder.resource_id.as_ref()))
+            .next()
+            .or(self.playlist_item.as_ref().and_then(|item| item.resource_id.as_ref()))
+    }
+}
@@ -13,6 +13,6 @@ pub struct RelatedPlaylists {
 
 impl ContentDetails {
     pub fn get_upload_playlist_id(&self) -> Option<String> {
-        return Some(self.related_playlists.uploads.clone())
+        Some(self.related_playlists.uploads.clone())
     }
 }
\ No newline at end of file
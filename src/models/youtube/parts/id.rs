@@ -18,7 +18,7 @@ impl Id {
     pub fn is_playlist(&self) -> bool { self.kind.is_playlist() }
 
     pub fn get_id(&self) -> String {
-        return (if self.is_channel() {
+        (if self.is_channel() {
             self.channel_id.as_ref().expect("Tried to get id for non channel")
         } else if self.is_video() {
             self.video_id.as_ref().expect("Tried to get id for non channel")
@@ -0,0 +1,22 @@
+use serde::Deserialize;
+use crate::models::live_details::LiveDetails;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LiveStreamingDetails {
+    scheduled_start_time: Option<String>,
+    actual_start_time: Option<String>,
+    concurrent_viewers: Option<String>,
+    active_live_chat_id: Option<String>,
+}
+
+impl LiveStreamingDetails {
+    pub fn into_live_details(self) -> LiveDetails {
+        LiveDetails::new(
+            self.scheduled_start_time,
+            self.actual_start_time,
+            self.concurrent_viewers.and_then(|viewers| viewers.parse().ok()),
+            self.active_live_chat_id,
+        )
+    }
+}
@@ -5,3 +5,5 @@ pub mod kind;
 pub mod snippet;
 pub mod stats;
 pub mod thumbnails;
+pub mod activity_details;
+pub mod live_streaming_details;
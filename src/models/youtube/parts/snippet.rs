@@ -11,5 +11,8 @@ pub struct Snippet {
     pub description: Option<String>,
     pub published_at: Option<String>,
     pub thumbnails: Option<Thumbnails>,
-    pub resource_id: Option<ResourceId>
+    pub resource_id: Option<ResourceId>,
+    pub category_id: Option<String>,
+    #[serde(rename = "type")]
+    pub activity_type: Option<String>,
 }
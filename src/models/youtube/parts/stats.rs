@@ -3,19 +3,38 @@ use serde::Deserialize;
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Stats {
-    video_count: String
+    #[serde(default)]
+    video_count: String,
+    view_count: Option<String>,
+    like_count: Option<String>,
+    comment_count: Option<String>,
 }
 
 impl Default for Stats {
     fn default() -> Self {
-        return Stats {
-            video_count: String::from("0")
-        };
+        Stats {
+            video_count: String::from("0"),
+            view_count: None,
+            like_count: None,
+            comment_count: None,
+        }
     }
 }
 
 impl Stats {
     pub fn get_video_count(&self) -> u64 {
-        return self.video_count.parse().unwrap_or(0);
+        self.video_count.parse().unwrap_or(0)
+    }
+
+    pub fn get_view_count(&self) -> Option<u64> {
+        self.view_count.as_ref().and_then(|count| count.parse().ok())
+    }
+
+    pub fn get_like_count(&self) -> Option<u64> {
+        self.like_count.as_ref().and_then(|count| count.parse().ok())
+    }
+
+    pub fn get_comment_count(&self) -> Option<u64> {
+        self.comment_count.as_ref().and_then(|count| count.parse().ok())
     }
 }
@@ -0,0 +1,40 @@
+/// Quota cost of YouTube Data API calls, keyed by resource and method
+/// Parts no longer affect the cost, see https://developers.google.com/youtube/v3/determine_quota_cost
+/// Anything missing falls back to the default for its method
+const COSTS: [(&str, &str, usize); 10] = [
+    ("search", "list", 100),
+    ("videos", "list", 1),
+    ("channels", "list", 1),
+    ("playlists", "list", 1),
+    ("playlistItems", "list", 1),
+    ("activities", "list", 1),
+    ("videoCategories", "list", 1),
+    ("i18nRegions", "list", 1),
+    ("i18nLanguages", "list", 1),
+    ("liveChat/messages", "list", 1),
+];
+
+const COST_LIST: usize = 1;
+const COST_WRITE: usize = 50;
+
+pub fn cost(resource: &str, method: &str) -> usize {
+    COSTS.iter()
+        .find(|(cost_resource, cost_method, _)| *cost_resource == resource && *cost_method == method)
+        .map(|(_, _, cost)| *cost)
+        .unwrap_or(if method == "list" { COST_LIST } else { COST_WRITE })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_costs() {
+        assert_eq!(cost("search", "list"), 100);
+        assert_eq!(cost("videos", "list"), 1);
+        assert_eq!(cost("channels", "list"), 1);
+        assert_eq!(cost("playlistItems", "list"), 1);
+        assert_eq!(cost("liveChat/messages", "list"), 1);
+        assert_eq!(cost("playlists", "update"), 50);
+    }
+}
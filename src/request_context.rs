@@ -0,0 +1,103 @@
+use std::sync::Arc;
+use std::time::Duration;
+use rocket::Request;
+use rocket::request::{FromRequest, Outcome};
+use crate::clients::{ClientQuota, Scope};
+use crate::error::{self, ApiError};
+use crate::budget::Budget;
+use crate::ApiKey;
+use crate::request_id::request_id;
+
+/// Who an upstream call is being made for, passed from the endpoints through to [YoutubeClient::request]
+pub struct RequestContext {
+    client: Option<Arc<ClientQuota>>,
+    request_id: String,
+    route: String,
+    budget: Budget,
+}
+
+impl RequestContext {
+    /// For calls the server makes on its own behalf, these aren't billed to any client
+    pub fn system() -> RequestContext {
+        RequestContext { client: None, request_id: String::from("system"), route: String::from("system"), budget: Budget::unlimited() }
+    }
+}
+
+impl RequestContext {
+    pub fn get_request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// `anonymous` when the server is running without client keys
+    pub fn get_client_name(&self) -> &str {
+        match &self.client {
+            Some(client) => client.get_name(),
+            None if self.request_id == "system" => "system",
+            None => "anonymous"
+        }
+    }
+
+    pub fn get_route(&self) -> &str {
+        &self.route
+    }
+
+    pub fn require(&self, scope: Scope) -> Result<(), ApiError> {
+        match &self.client {
+            Some(client) if !client.has_scope(scope) => Err(ApiError::Forbidden(format!("{} doesn't have the {:?} scope", client.get_name(), scope))),
+            _ => Ok(())
+        }
+    }
+
+    /// Checked against the request's budget before the client's, so the client isn't charged for a call that isn't made
+    pub fn reserve(&self, cost: usize) -> Result<(), ApiError> {
+        self.budget.reserve(cost)?;
+        match &self.client {
+            Some(client) => client.reserve(cost).inspect_err(|_| self.budget.refund(cost)),
+            None => Ok(())
+        }
+    }
+
+    /// Same client, request id and route without the budget, for work that outlives the request
+    pub fn detached(&self) -> RequestContext {
+        RequestContext { client: self.client.clone(), request_id: self.request_id.clone(), route: self.route.clone(), budget: Budget::unlimited() }
+    }
+
+    /// Before each attempt, so retries stop once the request's time is up
+    pub fn check_deadline(&self) -> Result<(), ApiError> {
+        self.budget.check_deadline()
+    }
+
+    pub fn remaining_time(&self) -> Option<Duration> {
+        self.budget.remaining()
+    }
+
+    /// Undoes [RequestContext::reserve] when no call reached YouTube or every attempt failed
+    pub fn refund(&self, cost: usize) {
+        self.budget.refund(cost);
+        if let Some(client) = &self.client {
+            client.refund(cost);
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestContext {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        request.guard::<ApiKey>().await
+            .and_then(|api_key| match Budget::from_request(request) {
+                Ok(budget) => Outcome::Success(RequestContext {
+                    client: api_key.get_client(),
+                    request_id: request_id(request),
+                    route: request.route().map(|route| route.uri.path().to_string()).unwrap_or(String::from("unmatched")),
+                    budget,
+                }),
+                Err(error) => {
+                    let status = error.get_status();
+                    error::set_guard_error(request, error);
+                    Outcome::Error((status, "Invalid budget".to_string()))
+                }
+            })
+    }
+}
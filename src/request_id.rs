@@ -0,0 +1,19 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use chrono::Utc;
+use rocket::Request;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+pub struct RequestId(String);
+
+impl RequestId {
+    fn generate() -> RequestId {
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed) & 0xffff;
+        RequestId(format!("{:x}-{:04x}", Utc::now().timestamp_millis(), count))
+    }
+}
+
+/// Returns the id for this request, generating one the first time it's needed
+pub fn request_id(request: &Request) -> String {
+    request.local_cache(RequestId::generate).0.clone()
+}
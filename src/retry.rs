@@ -0,0 +1,143 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use rand::Rng;
+use serde::Serialize;
+use chrono::{Utc, SecondsFormat};
+
+pub const MAX_ATTEMPTS: usize = 4;
+const BACKOFF_BASE_MS: u64 = 250;
+const BACKOFF_MAX_MS: u64 = 4000;
+const MAX_DIAGNOSTICS: usize = 50;
+
+/// Exponential backoff with full jitter, `attempt` is 0 based
+pub fn backoff(attempt: usize) -> Duration {
+    let ceiling = BACKOFF_MAX_MS.min(BACKOFF_BASE_MS << attempt.min(16));
+    Duration::from_millis(rand::thread_rng().gen_range(ceiling / 2, ceiling + 1))
+}
+
+/// Last few characters of a key so it can be identified in logs without leaking it
+pub fn mask_key(key: &str) -> String {
+    let visible: String = key.chars().rev().take(4).collect::<Vec<char>>().into_iter().rev().collect();
+    format!("…{}", visible)
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Attempt {
+    key: String,
+    label: String,
+    status: Option<u16>,
+    outcome: &'static str,
+    elapsed_ms: u128,
+}
+
+impl Attempt {
+    pub fn new(key: &str, label: &str, status: Option<u16>, outcome: &'static str, elapsed: Duration) -> Self {
+        Attempt {
+            key: mask_key(key),
+            label: label.to_string(),
+            status,
+            outcome,
+            elapsed_ms: elapsed.as_millis(),
+        }
+    }
+}
+
+impl Attempt {
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn get_status(&self) -> Option<u16> {
+        self.status
+    }
+
+    pub fn get_outcome(&self) -> &'static str {
+        self.outcome
+    }
+
+    pub fn get_elapsed(&self) -> Duration {
+        Duration::from_millis(self.elapsed_ms as u64)
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestDiagnostic {
+    date: String,
+    resource: String,
+    succeeded: bool,
+    attempts: Vec<Attempt>,
+}
+
+/// Keeps the most recent upstream requests that needed more than one attempt or failed
+pub struct Diagnostics {
+    entries: Mutex<VecDeque<RequestDiagnostic>>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics {
+            entries: Mutex::new(VecDeque::with_capacity(MAX_DIAGNOSTICS)),
+        }
+    }
+}
+
+impl Diagnostics {
+    pub fn record(&self, resource: &str, succeeded: bool, attempts: Vec<Attempt>) {
+        if succeeded && attempts.len() <= 1 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_DIAGNOSTICS {
+            entries.pop_front();
+        }
+        entries.push_back(RequestDiagnostic {
+            date: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            resource: resource.to_string(),
+            succeeded,
+            attempts,
+        });
+    }
+
+    pub fn get_entries(&self) -> Vec<RequestDiagnostic> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        for _ in 0..20 {
+            let first = backoff(0).as_millis() as u64;
+            let third = backoff(2).as_millis() as u64;
+            let capped = backoff(30).as_millis() as u64;
+            assert!((BACKOFF_BASE_MS / 2..=BACKOFF_BASE_MS).contains(&first));
+            assert!((BACKOFF_BASE_MS * 2..=BACKOFF_BASE_MS * 4).contains(&third));
+            assert!((BACKOFF_MAX_MS / 2..=BACKOFF_MAX_MS).contains(&capped));
+        }
+    }
+
+    #[test]
+    fn test_mask_key() {
+        assert_eq!(mask_key("AIzaSyExample1234"), "…1234");
+        assert_eq!(mask_key("ab"), "…ab");
+    }
+
+    #[test]
+    fn test_only_retried_or_failed_requests_are_recorded() {
+        //GIVEN empty diagnostics
+        let diagnostics = Diagnostics::new();
+        let attempt = || Attempt::new("key1", "key0", Some(200), "ok", Duration::from_millis(5));
+        //WHEN recording a clean success, a retried success and a failure
+        diagnostics.record("search", true, vec![attempt()]);
+        diagnostics.record("search", true, vec![attempt(), attempt()]);
+        diagnostics.record("search", false, vec![attempt()]);
+        //THEN only the last two are kept
+        assert_eq!(diagnostics.get_entries().len(), 2);
+    }
+}
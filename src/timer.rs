@@ -1,39 +1,70 @@
 use chrono::{Timelike, Utc, Duration, DateTime};
+use std::future::Future;
 use std::ops::Add;
-use std::thread;
-use std::sync::{Mutex, Arc};
-use crate::key_manager::KeyManager;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 
-const RESET_HOUR: u32 = 9;
+/// UTC, set from the config by [init]
+static RESET_HOUR: AtomicU32 = AtomicU32::new(9);
+static RESET_MINUTE: AtomicU32 = AtomicU32::new(1);
 
-pub fn start_reset_timer(key_manager: Arc<Mutex<KeyManager>>) {
-    let _handler = thread::spawn(move || {
-        let mut key_manager = key_manager.lock().unwrap();
+/// Sets the UTC time quotas are reset each day
+pub fn init(hour: u32, minute: u32) {
+    RESET_HOUR.store(hour, Ordering::Relaxed);
+    RESET_MINUTE.store(minute, Ordering::Relaxed);
+}
+
+/// Lets the health check see if the reset task has died
+pub struct TimerHandle {
+    alive: Arc<AtomicBool>,
+}
+
+impl TimerHandle {
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::Relaxed)
+    }
+}
+
+/// Dropped when the task exits, including by panicking
+struct AliveFlag(Arc<AtomicBool>);
+
+impl Drop for AliveFlag {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+pub fn start_reset_timer<F, T>(trigger: F) -> TimerHandle where F: Fn() -> T + Send + 'static, T: Future<Output = ()> + Send {
+    let alive = Arc::new(AtomicBool::new(true));
+    let flag = AliveFlag(alive.clone());
+    tokio::spawn(async move {
+        let _flag = flag;
         loop {
-            thread::sleep(std::time::Duration::from_millis(calc_wait_time(Utc::now())));
-            trigger(&mut key_manager);
+            tokio::time::sleep(std::time::Duration::from_millis(calc_wait_time(Utc::now()))).await;
+            trigger().await;
         }
     });
+    TimerHandle { alive }
+}
+
+pub fn seconds_until_reset() -> u64 {
+    (calc_wait_time(Utc::now()) / 1000).max(1)
 }
 
 fn calc_wait_time(now: DateTime<Utc>) -> u64 {
-    let reset_time: DateTime<Utc> = if now.hour() < RESET_HOUR {
-        Utc::now().with_hour(RESET_HOUR).unwrap()
-            .with_minute(1).unwrap()
-            .with_second(0).unwrap()
+    let (hour, minute) = (RESET_HOUR.load(Ordering::Relaxed), RESET_MINUTE.load(Ordering::Relaxed));
+    let today = now.with_hour(hour).unwrap()
+        .with_minute(minute).unwrap()
+        .with_second(0).unwrap()
+        .with_nanosecond(0).unwrap();
+    let reset_time: DateTime<Utc> = if (now.hour(), now.minute()) < (hour, minute) {
+        today
     } else {
-        Utc::now().with_hour(RESET_HOUR).unwrap()
-            .with_minute(1).unwrap()
-            .with_second(0).unwrap()
-            .add(Duration::days(1))
+        today.add(Duration::days(1))
     };
     0_i64.max(reset_time.timestamp_millis() - now.timestamp_millis()) as u64
 }
 
-fn trigger(key_manager: &mut KeyManager) {
-    key_manager.reset_keys()
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -59,4 +90,22 @@ mod test {
 
         assert!(ms > 82859000 && ms < 82860999)
     }
-}
\ No newline at end of file
+
+    #[tokio::test(start_paused = true)]
+    async fn check_handle_reports_dead_task() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let handle = start_reset_timer(move || {
+            let sender = sender.clone();
+            async move {
+                sender.send(()).unwrap();
+                panic!("reset failed");
+            }
+        });
+        assert!(handle.is_alive());
+
+        receiver.recv().await.unwrap();
+        tokio::task::yield_now().await;
+
+        assert!(!handle.is_alive());
+    }
+}
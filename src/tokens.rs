@@ -0,0 +1,94 @@
+use std::fs;
+use std::path::PathBuf;
+use anyhow::{Result, Context};
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use crate::clients::Scope;
+
+/// Claims the minting backend must include, `exp` is checked by [Validation]
+#[derive(Debug, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    #[allow(dead_code)]
+    pub exp: u64,
+    #[serde(default)]
+    pub scopes: Vec<Scope>,
+}
+
+/// Verifies signed bearer tokens locally, using either a shared HS256 secret or an RS256 public key
+pub struct TokenVerifier {
+    key: DecodingKey<'static>,
+    validation: Validation,
+}
+
+impl TokenVerifier {
+    pub fn hs256(secret: &str, issuer: Option<String>, audience: Option<String>) -> TokenVerifier {
+        let key = DecodingKey::from_secret(secret.as_bytes()).into_static();
+        TokenVerifier::new(key, Algorithm::HS256, issuer, audience)
+    }
+
+    pub fn rs256(public_key_file: &PathBuf, issuer: Option<String>, audience: Option<String>) -> Result<TokenVerifier> {
+        let pem = fs::read(public_key_file).with_context(|| format!("Unable to read {}", public_key_file.display()))?;
+        let key = DecodingKey::from_rsa_pem(&pem).with_context(|| format!("Invalid RSA public key {}", public_key_file.display()))?.into_static();
+        Ok(TokenVerifier::new(key, Algorithm::RS256, issuer, audience))
+    }
+
+    fn new(key: DecodingKey<'static>, algorithm: Algorithm, issuer: Option<String>, audience: Option<String>) -> TokenVerifier {
+        let mut validation = Validation::new(algorithm);
+        validation.iss = issuer;
+        if let Some(audience) = audience {
+            validation.set_audience(&[audience]);
+        }
+        TokenVerifier { key, validation }
+    }
+}
+
+impl TokenVerifier {
+    /// Returns None for tokens that are badly signed, expired or for a different issuer or audience
+    pub fn verify(&self, token: &str) -> Option<Claims> {
+        decode::<Claims>(token, &self.key, &self.validation)
+            .map(|data| data.claims)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde::Serialize;
+    use chrono::Utc;
+
+    #[derive(Serialize)]
+    struct TestClaims {
+        sub: &'static str,
+        exp: i64,
+        iss: &'static str,
+        aud: &'static str,
+        scopes: Vec<&'static str>,
+    }
+
+    /// HS256 token signed with `secret`, expiring `expires_in` seconds from now
+    pub fn make_token(secret: &str, scopes: Vec<&'static str>, audience: &'static str, expires_in: i64) -> String {
+        let claims = TestClaims { sub: "frontend", exp: Utc::now().timestamp() + expires_in, iss: "backend", aud: audience, scopes };
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn test_verify_hs256() {
+        //GIVEN verifier for the backend issuer and proxy audience
+        let verifier = TokenVerifier::hs256("secret", Some(String::from("backend")), Some(String::from("proxy")));
+        //WHEN verifying valid, expired, wrongly signed and wrong audience tokens
+        let valid = verifier.verify(&make_token("secret", vec!["read", "search"], "proxy", 60));
+        let expired = verifier.verify(&make_token("secret", vec!["read"], "proxy", -120));
+        let wrong_secret = verifier.verify(&make_token("other", vec!["read"], "proxy", 60));
+        let wrong_audience = verifier.verify(&make_token("secret", vec!["read"], "other", 60));
+        //THEN only the valid token is accepted with its scopes
+        let claims = valid.unwrap();
+        assert_eq!(claims.sub, "frontend");
+        assert_eq!(claims.scopes, vec![Scope::Read, Scope::Search]);
+        assert!(expired.is_none());
+        assert!(wrong_secret.is_none());
+        assert!(wrong_audience.is_none());
+    }
+}
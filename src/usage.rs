@@ -0,0 +1,285 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use rocket::{Orbit, Request, Response, Rocket, State};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::outcome::Outcome;
+use crate::date_util::DateExt;
+use crate::logger;
+use crate::models::usage::{Ranking, UsageReport};
+use crate::youtube_manager::YoutubeManager;
+
+/// Older months are dropped from the ledger
+pub const MAX_MONTHS: usize = 24;
+/// Distinct channels or queries counted per month, after this only ones already seen are counted
+const MAX_TRACKED: usize = 10_000;
+const TOP_COUNT: usize = 10;
+const SAVE_INTERVAL: u64 = 60;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+struct MonthUsage {
+    quota_spent: usize,
+    requests_served: usize,
+    cache_hits: usize,
+    cache_savings: usize,
+    channels: HashMap<String, usize>,
+    queries: HashMap<String, usize>,
+}
+
+struct Ledger {
+    /// Keyed by `YYYY-MM`
+    months: BTreeMap<String, MonthUsage>,
+    changed: bool,
+}
+
+/// Running totals for each calendar month, saved to `path` every minute so they survive restarts
+pub struct UsageLedger {
+    path: Option<PathBuf>,
+    ledger: Mutex<Ledger>,
+}
+
+impl UsageLedger {
+    pub fn new(path: Option<PathBuf>) -> Result<UsageLedger> {
+        let months = match &path {
+            Some(path) if path.exists() => {
+                let json = fs::read_to_string(path).with_context(|| format!("Unable to read {}", path.display()))?;
+                serde_json::from_str(&json).with_context(|| format!("Invalid usage file {}", path.display()))?
+            }
+            _ => BTreeMap::new()
+        };
+        Ok(UsageLedger {
+            path,
+            ledger: Mutex::new(Ledger { months, changed: false }),
+        })
+    }
+}
+
+impl UsageLedger {
+    pub fn record_spend(&self, cost: usize) {
+        self.update(Utc::now(), |month| month.quota_spent += cost);
+    }
+
+    pub fn record_request(&self) {
+        self.update(Utc::now(), |month| month.requests_served += 1);
+    }
+
+    pub fn record_cache_hit(&self, saved: usize) {
+        self.update(Utc::now(), |month| {
+            month.cache_hits += 1;
+            month.cache_savings += saved;
+        });
+    }
+
+    pub fn record_channel(&self, id: &str) {
+        self.update(Utc::now(), |month| count(&mut month.channels, id.to_string()));
+    }
+
+    pub fn record_query(&self, query: &str) {
+        self.update(Utc::now(), |month| count(&mut month.queries, query.trim().to_lowercase()));
+    }
+
+    fn update<F: FnOnce(&mut MonthUsage)>(&self, now: DateTime<Utc>, change: F) {
+        let mut ledger = self.ledger.lock().unwrap();
+        change(ledger.months.entry(month_key(&now)).or_default());
+        while ledger.months.len() > MAX_MONTHS {
+            let oldest = ledger.months.keys().next().cloned().unwrap();
+            ledger.months.remove(&oldest);
+        }
+        ledger.changed = true;
+    }
+
+    /// Usage for the calendar month containing `date`, empty if nothing was recorded
+    pub fn get_report(&self, date: DateTime<Utc>) -> UsageReport {
+        let (from, to) = date.into_month_surround();
+        let ledger = self.ledger.lock().unwrap();
+        let month = ledger.months.get(&month_key(&from)).cloned().unwrap_or_default();
+        UsageReport::new(
+            month_key(&from),
+            from.to_rfc3339_opts(SecondsFormat::Secs, true),
+            to.to_rfc3339_opts(SecondsFormat::Secs, true),
+            month.quota_spent,
+            month.requests_served,
+            month.cache_hits,
+            month.cache_savings,
+            top(&month.channels),
+            top(&month.queries),
+        )
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(path) = &self.path {
+            let json = {
+                let mut ledger = self.ledger.lock().unwrap();
+                if !ledger.changed {
+                    return Ok(());
+                }
+                ledger.changed = false;
+                serde_json::to_string(&ledger.months)?
+            };
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, json).with_context(|| format!("Unable to write {}", tmp_path.display()))?;
+            fs::rename(&tmp_path, path).with_context(|| format!("Unable to write {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// [UsageLedger::save] on a blocking thread so the write doesn't hold up an async worker
+    pub async fn save_in_background(ledger: Arc<UsageLedger>) -> Result<()> {
+        tokio::task::spawn_blocking(move || ledger.save()).await?
+    }
+
+    pub fn start_saving(ledger: Arc<UsageLedger>) {
+        if ledger.path.is_none() {
+            return;
+        }
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(SAVE_INTERVAL)).await;
+                if let Err(error) = UsageLedger::save_in_background(ledger.clone()).await {
+                    logger::error(None, "Unable to save usage", &[("error", format!("{:?}", error))]);
+                }
+            }
+        });
+    }
+}
+
+fn month_key(date: &DateTime<Utc>) -> String {
+    date.format("%Y-%m").to_string()
+}
+
+fn count(counts: &mut HashMap<String, usize>, value: String) {
+    if value.is_empty() {
+        return;
+    }
+    if counts.len() < MAX_TRACKED || counts.contains_key(&value) {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+}
+
+fn top(counts: &HashMap<String, usize>) -> Vec<Ranking> {
+    let mut entries: Vec<(&String, &usize)> = counts.iter().collect();
+    entries.sort_by(|(a_value, a_count), (b_value, b_count)| b_count.cmp(a_count).then(a_value.cmp(b_value)));
+    entries.into_iter()
+        .take(TOP_COUNT)
+        .map(|(value, count)| Ranking::new(value.clone(), *count))
+        .collect()
+}
+
+/// Counts successful responses from the data endpoints, admin and health requests aren't counted,
+/// and saves the ledger on shutdown so the last minute isn't lost
+pub struct UsageFairing {}
+
+#[rocket::async_trait]
+impl Fairing for UsageFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Usage",
+            kind: Kind::Response | Kind::Shutdown,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let is_data_route = request.route()
+            .map(|route| route.uri.path().starts_with("/v1/") && !route.uri.path().starts_with("/v1/admin/"))
+            .unwrap_or(false);
+        if !is_data_route || response.status().code >= 400 {
+            return;
+        }
+        if let Outcome::Success(youtube_manager) = request.guard::<&State<YoutubeManager>>().await {
+            youtube_manager.get_usage().record_request();
+        }
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        if let Some(youtube_manager) = rocket.state::<YoutubeManager>() {
+            if let Err(error) = youtube_manager.save_usage().await {
+                logger::error(None, "Unable to save usage", &[("error", format!("{:?}", error))]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_months_are_reported_separately() {
+        //GIVEN a ledger with usage in two months
+        let ledger = UsageLedger::new(None).unwrap();
+        let may: DateTime<Utc> = DateTime::from_str("2020-05-31T23:00:00Z").unwrap();
+        let june: DateTime<Utc> = DateTime::from_str("2020-06-15T10:00:00Z").unwrap();
+        ledger.update(may, |month| month.quota_spent += 100);
+        ledger.update(june, |month| month.quota_spent += 3);
+        ledger.update(june, |month| month.requests_served += 1);
+        //WHEN reporting each month and one without usage
+        let may_report = ledger.get_report(may);
+        let june_report = ledger.get_report(june);
+        let july_report = ledger.get_report(DateTime::from_str("2020-07-01T00:00:00Z").unwrap());
+        //THEN
+        assert_eq!(may_report.get_month(), "2020-05");
+        assert_eq!(may_report.get_quota_spent(), 100);
+        assert_eq!(june_report.get_quota_spent(), 3);
+        assert_eq!(june_report.get_requests_served(), 1);
+        assert_eq!(july_report.get_month(), "2020-07");
+        assert_eq!(july_report.get_quota_spent(), 0);
+    }
+
+    #[test]
+    fn test_top_queries_are_ranked() {
+        //GIVEN a ledger with repeated queries
+        let ledger = UsageLedger::new(None).unwrap();
+        for query in &["rust", "Rust ", "music", "rust", "music", "news"] {
+            ledger.record_query(query);
+        }
+        //WHEN reporting this month
+        let report = ledger.get_report(Utc::now());
+        //THEN queries are normalised and ordered by count
+        assert_eq!(report.get_top_queries(), &[
+            Ranking::new(String::from("rust"), 3),
+            Ranking::new(String::from("music"), 2),
+            Ranking::new(String::from("news"), 1)]);
+    }
+
+    #[test]
+    fn test_oldest_months_are_dropped() {
+        //GIVEN a ledger with usage in more months than are kept
+        let ledger = UsageLedger::new(None).unwrap();
+        let start: DateTime<Utc> = DateTime::from_str("2018-01-15T10:00:00Z").unwrap();
+        for i in 0..(MAX_MONTHS as u32 + 2) {
+            ledger.update(start + chrono::Duration::days(31 * i as i64), |month| month.quota_spent += 1);
+        }
+        //WHEN reporting the first and last months
+        let first = ledger.get_report(start);
+        let last = ledger.get_report(start + chrono::Duration::days(31 * (MAX_MONTHS as i64 + 1)));
+        //THEN only the newest are kept
+        assert_eq!(ledger.ledger.lock().unwrap().months.len(), MAX_MONTHS);
+        assert_eq!(first.get_quota_spent(), 0);
+        assert_eq!(last.get_quota_spent(), 1);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        //GIVEN a ledger saved to a temp file
+        let mut path = std::env::temp_dir();
+        path.push(format!("usage_test_{}.json", std::process::id()));
+        let ledger = UsageLedger::new(Some(path.clone())).unwrap();
+        ledger.record_spend(100);
+        ledger.record_cache_hit(1);
+        ledger.save().unwrap();
+        //WHEN it's loaded again
+        let loaded = UsageLedger::new(Some(path.clone())).unwrap();
+        fs::remove_file(path).unwrap();
+        //THEN the totals are kept
+        let report = loaded.get_report(Utc::now());
+        assert_eq!(report.get_quota_spent(), 100);
+        assert_eq!(report.get_cache_savings(), 1);
+    }
+}
@@ -1,52 +1,165 @@
-use std::sync::{Mutex, Arc};
-use reqwest::blocking::{Client, Response};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Mutex as AsyncMutex;
 use anyhow::{Error, Result};
-use crate::key_manager::KeyManager;
+use futures::future::join_all;
+use serde::de::DeserializeOwned;
+use crate::key_manager::{KeyManager, KeyAction};
 use reqwest::Url;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use crate::models::content_type::ContentType;
-use crate::models::youtube::{SearchResponse, ListResponse, PlaylistResponse};
+use crate::models::key_status::KeyStatus;
+use crate::key_store::KeyStore;
+use crate::quota;
+use crate::request_context::RequestContext;
+use crate::models::reconciliation::Reconciliation;
+use crate::models::youtube::{SearchResponse, ListResponse, PlaylistResponse, CategoryResponse, I18nResponse, ActivityResponse, LiveResponse, LiveChatResponse};
 use crate::models::youtube::items::search_item::SearchItem;
 use crate::models::youtube::items::list_item::ListItem;
 use crate::models::youtube::items::playlist_item::PlaylistItem;
-use crate::timer::start_reset_timer;
+use crate::models::youtube::items::category_item::CategoryItem;
+use crate::models::youtube::items::i18n_item::I18nItem;
+use crate::models::youtube::items::activity_item::ActivityItem;
+use crate::models::youtube::items::live_item::LiveItem;
+use crate::timer::{start_reset_timer, TimerHandle};
+use crate::health::UpstreamHealth;
+use crate::models::health::{HealthCheck, HealthReport, HealthStatus};
+use crate::error::ApiError;
+use crate::models::youtube::error::ErrorResponse;
+use crate::retry::{MAX_ATTEMPTS, Attempt, Diagnostics, RequestDiagnostic, backoff, mask_key};
+use std::time::Instant;
+use std::future::Future;
+use crate::metrics::METRICS;
+use crate::logger;
+use crate::audit::{AuditLog, AuditRecord, Period};
+use crate::models::spend::SpendReport;
+use chrono::{DateTime, Utc};
+use crate::usage::UsageLedger;
+use crate::models::key_test::KeyTest;
+use crate::http_client::HttpClient;
 
-pub const YOUTUBE_URL: &'static str = "https://www.googleapis.com/youtube/v3";
-
-const COST_SEARCH: usize = 100;
-const COST_SINGLE: usize = 6;
-const COST_PLAYLIST_PAGE: usize = 3;
+pub const YOUTUBE_URL: &str = "https://www.googleapis.com/youtube/v3";
 
 pub struct YoutubeClient {
-    key_manager: Arc<Mutex<KeyManager>>,
-    client: Client,
+    /// Never held across I/O, so callers don't wait on YouTube or the disk
+    key_manager: Arc<AsyncMutex<KeyManager>>,
+    /// Serialises key file writes
+    key_saving: AsyncMutex<()>,
+    client: HttpClient,
     base_url: String,
+    diagnostics: Diagnostics,
+    key_store: Option<KeyStore>,
+    upstream_health: UpstreamHealth,
+    reset_timer: Mutex<Option<TimerHandle>>,
+    audit_log: Option<Arc<AuditLog>>,
+    usage: Arc<UsageLedger>,
 }
 
 impl YoutubeClient {
-    pub fn new(key_manager: KeyManager, base_url: String, client: Client) -> YoutubeClient {
-        return YoutubeClient {
-            key_manager: Arc::new(Mutex::new(key_manager)),
+    pub fn new(key_manager: KeyManager, base_url: String, client: HttpClient, key_store: Option<KeyStore>, audit_log: Option<AuditLog>, usage: Arc<UsageLedger>) -> YoutubeClient {
+        YoutubeClient {
+            key_manager: Arc::new(AsyncMutex::new(key_manager)),
+            key_saving: AsyncMutex::new(()),
             client,
             base_url,
-        };
+            diagnostics: Diagnostics::new(),
+            key_store,
+            upstream_health: UpstreamHealth::new(),
+            reset_timer: Mutex::new(None),
+            audit_log: audit_log.map(Arc::new),
+            usage,
+        }
     }
 }
 
 impl YoutubeClient {
-    pub fn get_key_status(&self) -> HashMap<usize, usize> {
-        self.key_manager.lock().unwrap().get_status()
+    pub async fn get_key_status(&self) -> HashMap<usize, KeyStatus> {
+        self.key_manager.lock().await.get_status()
+    }
+
+    pub async fn get_quota_usage(&self) -> Vec<(String, usize, usize)> {
+        self.key_manager.lock().await.get_quota_usage()
+    }
+
+    pub async fn get_reconciliation(&self) -> Reconciliation {
+        self.key_manager.lock().await.get_reconciliation()
+    }
+
+    /// Applies `change` to the key pool and, if it returns true, persists the pool
+    pub async fn update_keys<F: FnOnce(&mut KeyManager) -> bool>(&self, change: F) -> Result<bool> {
+        let changed = change(&mut *self.key_manager.lock().await);
+        if changed {
+            self.persist_keys().await?;
+        }
+        Ok(changed)
     }
 
-    pub fn reset_key_status(&self) {
-        self.key_manager.lock().unwrap().reset_keys();
+    /// Saves the pool as it is once the previous save finishes, so saves can't land out of order and leave stale keys on disk
+    async fn persist_keys(&self) -> Result<()> {
+        if let Some(store) = &self.key_store {
+            let _saving = self.key_saving.lock().await;
+            let entries = self.key_manager.lock().await.get_entries();
+            let store = store.clone();
+            tokio::task::spawn_blocking(move || store.save(&entries)).await??;
+        }
+        Ok(())
     }
 
-    pub fn start_timer(&self) {
-        start_reset_timer(self.key_manager.clone())
+    pub fn start_key_reload_listener(&self) -> Result<()> {
+        if let Some(store) = &self.key_store {
+            store.start_reload_on_hangup(self.key_manager.clone())?;
+        }
+        Ok(())
+    }
+
+    pub async fn reset_key_status(&self) {
+        self.key_manager.lock().await.reset_keys();
+    }
+
+    /// Resets the keys every day, `on_reset` is called afterwards for anything else tracking daily quota
+    pub fn start_timer<F: Fn() + Send + Sync + 'static>(&self, on_reset: F) {
+        let key_manager = self.key_manager.clone();
+        let on_reset = Arc::new(on_reset);
+        let handle = start_reset_timer(move || {
+            let key_manager = key_manager.clone();
+            let on_reset = on_reset.clone();
+            async move {
+                key_manager.lock().await.reset_keys();
+                on_reset();
+            }
+        });
+        *self.reset_timer.lock().unwrap() = Some(handle);
+    }
+
+    pub async fn get_health(&self) -> HealthReport {
+        let mut checks = BTreeMap::new();
+
+        let search_cost = quota::cost("search", "list");
+        let (for_search, for_list) = {
+            let key_manager = self.key_manager.lock().await;
+            (key_manager.count_available(search_cost), key_manager.count_available(1))
+        };
+        let keys = if for_search > 0 {
+            HealthCheck::new(HealthStatus::Ok, format!("{} keys can afford a search", for_search))
+        } else if for_list > 0 {
+            HealthCheck::new(HealthStatus::Degraded, format!("No keys can afford a search, {} can afford other requests", for_list))
+        } else {
+            HealthCheck::new(HealthStatus::Unhealthy, String::from("No keys have quota left"))
+        };
+        checks.insert("keys", keys);
+
+        checks.insert("upstream", self.upstream_health.check());
+
+        let reset_timer = match self.reset_timer.lock().unwrap().as_ref() {
+            Some(handle) if handle.is_alive() => HealthCheck::new(HealthStatus::Ok, String::from("Running")),
+            Some(_) => HealthCheck::new(HealthStatus::Unhealthy, String::from("Reset task has stopped, quotas won't be reset")),
+            None => HealthCheck::new(HealthStatus::Unhealthy, String::from("Reset task hasn't been started")),
+        };
+        checks.insert("resetTimer", reset_timer);
+
+        HealthReport::new(checks)
     }
 
-    pub fn playlist_page(&self, search_params: Vec<(&'static str, String)>) -> Result<(Vec<PlaylistItem>, Option<String>)> {
+    pub async fn playlist_page(&self, ctx: &RequestContext, search_params: Vec<(&'static str, String)>) -> Result<(Vec<PlaylistItem>, Option<String>)> {
         let mut params = vec![
             ("part", String::from("id,snippet")),
             ("maxResults", String::from("50"))];
@@ -55,13 +168,12 @@ impl YoutubeClient {
             params.push((key, value.clone()));
         }
 
-        self.request(COST_PLAYLIST_PAGE, "playlist items", params, "playlistItems", |resp| {
-            let response = resp.json::<PlaylistResponse>()?;
-            Ok(Some((response.items, response.next_page_token)))
-        }).map(|result| result.unwrap())
+        self.request(ctx, "playlist items", params, "playlistItems", |response: PlaylistResponse| {
+            Some((response.items, response.next_page_token))
+        }).await.map(|result| result.unwrap())
     }
 
-    pub fn single(&self, content_type: ContentType, id: String) -> Result<Option<ListItem>> {
+    pub async fn single(&self, ctx: &RequestContext, content_type: ContentType, id: String) -> Result<Option<ListItem>> {
         let mut params: Vec<(&str, String)> = vec![("id", id)];
 
         let path;
@@ -81,22 +193,12 @@ impl YoutubeClient {
             }
         }
 
-        self.request(COST_SINGLE, "single", params, path, |resp| {
-            let response = resp.json::<ListResponse>()?;
-            if response.items.is_some() {
-                let mut list = response.items.unwrap();
-                if list.len() > 0 {
-                    Ok(Some(list.remove(0)))
-                } else {
-                    Ok(None)
-                }
-            } else {
-                Ok(None)
-            }
-        })
+        self.request(ctx, "single", params, path, |response: ListResponse| {
+            response.items.and_then(|list| list.into_iter().next())
+        }).await
     }
 
-    pub fn search(&self, content_type: ContentType, search_params: Vec<(&'static str, String)>) -> Result<Vec<SearchItem>> {
+    pub async fn search(&self, ctx: &RequestContext, content_type: ContentType, search_params: Vec<(&'static str, String)>) -> Result<Vec<SearchItem>> {
         let mut params = vec![
             ("part", String::from("snippet")),
             ("maxResults", String::from("50")),
@@ -116,40 +218,308 @@ impl YoutubeClient {
             ContentType::PLAYLIST => params.push(("type", String::from("playlist"))),
         }
 
-        self.request(COST_SEARCH, "search", params, "search", |resp| {
-            Ok(Some(resp.json::<SearchResponse>()?.items))
-        }).map(|result| result.unwrap())
+        self.request(ctx, "search", params, "search", |response: SearchResponse| {
+            Some(response.items)
+        }).await.map(|result| result.unwrap())
     }
 
-    pub fn request<T, F: Fn(Response) -> Result<Option<T>>>(&self, cost: usize, key_error_name: &'static str, mut params: Vec<(&'static str, String)>, path: &str, response_handler: F) -> Result<Option<T>> {
-        let key = self.key_manager.lock().unwrap().get_key(cost);
+    pub async fn most_popular(&self, ctx: &RequestContext, search_params: Vec<(&'static str, String)>) -> Result<(Vec<ListItem>, Option<String>)> {
+        let mut params = vec![
+            ("part", String::from("id,snippet,statistics")),
+            ("chart", String::from("mostPopular")),
+            ("maxResults", String::from("50"))];
 
-        if let None = key {
-            return Err(Error::msg(format!("No keys available for {}", key_error_name)));
+        for (key, value) in &search_params {
+            params.push((key, value.clone()));
         }
 
-        let key = key.unwrap();
+        self.request(ctx, "most popular", params, "videos", |response: ListResponse| {
+            Some((response.items.unwrap_or(vec![]), response.next_page_token))
+        }).await.map(|result| result.unwrap())
+    }
 
-        params.push(("key", key.clone()));
+    pub async fn live_details(&self, ctx: &RequestContext, ids: Vec<String>) -> Result<Vec<LiveItem>> {
+        let params = vec![
+            ("part", String::from("id,liveStreamingDetails")),
+            ("id", ids.join(","))];
 
-        let resp = self.client
-            .get(Url::parse_with_params(&format!("{}/{}", self.base_url, path), &params)?)
-            .send();
+        self.request(ctx, "live details", params, "videos", |response: LiveResponse| {
+            Some(response.items)
+        }).await.map(|result| result.unwrap())
+    }
 
-        match resp {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    response_handler(resp)
-                } else if resp.status().as_u16() == 429 {
-                    self.key_manager.lock().unwrap().set_key_as_expired(&key);
-                    self.request(cost, key_error_name, params, path, response_handler)
-                } else {
-                    let status_code = resp.status().as_u16();
-                    eprintln!("{} Error: {}\n{}", key_error_name, status_code, resp.text().unwrap_or(String::from("Unable to parse response")));
-                    Err(Error::msg(format!("Error getting {}: {}", key_error_name, status_code)))
+    pub async fn live_chat_messages(&self, ctx: &RequestContext, chat_id: String, page_token: Option<String>) -> Result<LiveChatResponse> {
+        let mut params = vec![
+            ("part", String::from("id,snippet,authorDetails")),
+            ("liveChatId", chat_id)];
+
+        if let Some(token) = page_token {
+            params.push(("pageToken", token));
+        }
+
+        self.request(ctx, "live chat", params, "liveChat/messages", |response: LiveChatResponse| {
+            Some(response)
+        }).await.map(|result| result.unwrap())
+    }
+
+    pub async fn activities(&self, ctx: &RequestContext, search_params: Vec<(&'static str, String)>) -> Result<Vec<ActivityItem>> {
+        let mut params = vec![
+            ("part", String::from("snippet,contentDetails")),
+            ("maxResults", String::from("50"))];
+
+        for (key, value) in &search_params {
+            params.push((key, value.clone()));
+        }
+
+        self.request(ctx, "activities", params, "activities", |response: ActivityResponse| {
+            Some(response.items)
+        }).await.map(|result| result.unwrap())
+    }
+
+    pub async fn video_categories(&self, ctx: &RequestContext, region: String) -> Result<Vec<CategoryItem>> {
+        let params = vec![
+            ("part", String::from("snippet")),
+            ("regionCode", region)];
+
+        self.request(ctx, "categories", params, "videoCategories", |response: CategoryResponse| {
+            Some(response.items)
+        }).await.map(|result| result.unwrap())
+    }
+
+    pub async fn i18n_regions(&self, ctx: &RequestContext) -> Result<Vec<I18nItem>> {
+        self.i18n(ctx, "regions", "i18nRegions").await
+    }
+
+    pub async fn i18n_languages(&self, ctx: &RequestContext) -> Result<Vec<I18nItem>> {
+        self.i18n(ctx, "languages", "i18nLanguages").await
+    }
+
+    async fn i18n(&self, ctx: &RequestContext, key_error_name: &'static str, path: &str) -> Result<Vec<I18nItem>> {
+        let params = vec![("part", String::from("snippet"))];
+
+        self.request(ctx, key_error_name, params, path, |response: I18nResponse| {
+            Some(response.items)
+        }).await.map(|result| result.unwrap())
+    }
+
+    /// Calls i18nLanguages, one of the cheapest calls, with every key at once
+    /// These calls skip the pool so nothing is recorded and no key is changed
+    pub async fn test_keys(&self) -> Vec<KeyTest> {
+        let entries = self.key_manager.lock().await.get_entries();
+        let url = format!("{}/i18nLanguages", self.base_url);
+        join_all(entries.into_iter()
+            .map(|entry| async {
+                let params = [("part", "snippet"), ("key", entry.key.as_str())];
+                let resp = match Url::parse_with_params(&url, &params) {
+                    Ok(url) => self.client.get(url).send().await.map_err(Error::from),
+                    Err(error) => Err(Error::from(error))
+                };
+                match resp {
+                    Ok(resp) if resp.status().is_success() => KeyTest::new(entry.label, "ok", Some(resp.status().as_u16()), vec![], entry.disabled_reason),
+                    Ok(resp) => {
+                        let status = resp.status().as_u16();
+                        let reasons = resp.text().await.ok()
+                            .and_then(|body| serde_json::from_str::<ErrorResponse>(&body).ok())
+                            .map(|error| error.get_reasons())
+                            .unwrap_or(vec![]);
+                        let outcome = match KeyAction::from_response(status, &reasons) {
+                            KeyAction::Exhausted => "exhausted",
+                            KeyAction::RateLimited => "rate_limited",
+                            KeyAction::Disable => "invalid",
+                            KeyAction::PassThrough => "error",
+                        };
+                        KeyTest::new(entry.label, outcome, Some(status), reasons, entry.disabled_reason)
+                    }
+                    Err(error) => KeyTest::new(entry.label, "request_error", None, vec![error.to_string()], entry.disabled_reason)
                 }
+            })).await
+    }
+
+    pub fn get_diagnostics(&self) -> Vec<RequestDiagnostic> {
+        self.diagnostics.get_entries()
+    }
+
+    /// Reads an audit file per day, on a blocking thread
+    pub async fn get_spend(&self, from: DateTime<Utc>, to: DateTime<Utc>, period: Period) -> Result<SpendReport, ApiError> {
+        match &self.audit_log {
+            Some(audit_log) => {
+                let audit_log = audit_log.clone();
+                let report = tokio::task::spawn_blocking(move || audit_log.spend(from, to, period)).await.map_err(Error::from)?;
+                Ok(report?)
+            }
+            None => Err(ApiError::NotFound(String::from("Audit log isn't enabled, set AUDIT_LOG_DIR")))
+        }
+    }
+
+    /// Calls `path` with the next key, retrying with others, and passes the parsed response to `response_handler`
+    pub async fn request<R: DeserializeOwned, T, F: FnOnce(R) -> Option<T>>(&self, ctx: &RequestContext, key_error_name: &'static str, params: Vec<(&'static str, String)>, path: &str, response_handler: F) -> Result<Option<T>> {
+        let cost = quota::cost(path, "list");
+        // once per request rather than per attempt, retries are on the proxy not the client
+        ctx.reserve(cost)?;
+        let mut attempts: Vec<Attempt> = Vec::with_capacity(MAX_ATTEMPTS);
+        let result = self.request_with_retries(ctx, key_error_name, params, path, response_handler, &mut attempts).await;
+        if result.is_err() {
+            ctx.refund(cost);
+        }
+        for attempt in &attempts {
+            let status = attempt.get_status().map(|status| status.to_string()).unwrap_or(attempt.get_outcome().to_string());
+            logger::debug(Some(ctx.get_request_id()), "Upstream call", &[
+                ("resource", path.to_string()),
+                ("status", status.clone()),
+                ("outcome", attempt.get_outcome().to_string()),
+                ("elapsedMs", attempt.get_elapsed().as_millis().to_string())]);
+            METRICS.record_upstream(path, status, attempt.get_elapsed());
+            self.upstream_health.record(["server_error", "timeout", "connection_error"].contains(&attempt.get_outcome()));
+            // calls cut off before youtube answered aren't known to have cost anything
+            if attempt.get_status().is_some() {
+                self.usage.record_spend(cost);
             }
-            Err(err) => Err(Error::from(err))
         }
+        if let Some(audit_log) = &self.audit_log {
+            let records: Vec<AuditRecord> = attempts.iter().map(|attempt| AuditRecord::new(ctx, path, cost, attempt)).collect();
+            let audit_log = audit_log.clone();
+            let written = tokio::task::spawn_blocking(move || records.iter().try_for_each(|record| audit_log.record(record))).await;
+            if let Err(error) = written.map_err(Error::from).and_then(|result| result) {
+                logger::error(Some(ctx.get_request_id()), "Unable to write audit record", &[("error", format!("{:?}", error))]);
+            }
+        }
+        METRICS.record_retries(path, attempts.len().saturating_sub(1));
+        self.diagnostics.record(key_error_name, result.is_ok(), attempts);
+        result
+    }
+
+    async fn request_with_retries<R: DeserializeOwned, T, F: FnOnce(R) -> Option<T>>(&self, ctx: &RequestContext, key_error_name: &'static str, params: Vec<(&'static str, String)>, path: &str, response_handler: F, attempts: &mut Vec<Attempt>) -> Result<Option<T>> {
+        let url = format!("{}/{}", self.base_url, path);
+        let cost = quota::cost(path, "list");
+        let mut last_error = ApiError::QuotaExhausted(format!("No keys available for {}", key_error_name));
+
+        for attempt in 0..MAX_ATTEMPTS {
+            ctx.check_deadline()?;
+            let (key, label) = {
+                let mut key_manager = self.key_manager.lock().await;
+                match key_manager.get_key(cost) {
+                    Some(key) => {
+                        let label = key_manager.get_label(&key).unwrap_or_default();
+                        (key, label)
+                    }
+                    None => return Err(Error::from(ApiError::QuotaExhausted(format!("No keys available for {}", key_error_name))))
+                }
+            };
+
+            let mut attempt_params = params.clone();
+            attempt_params.push(("key", key.clone()));
+
+            let started = Instant::now();
+            let request = self.client.get(Url::parse_with_params(&url, &attempt_params)?);
+            let resp = match within_deadline(ctx, async {
+                let resp = request.send().await?;
+                let status = resp.status();
+                resp.text().await.map(|body| (status, body))
+            }).await {
+                Some(resp) => resp,
+                None => {
+                    attempts.push(Attempt::new(&key, &label, None, "budget_exhausted", started.elapsed()));
+                    return Err(Error::from(ApiError::BudgetExhausted(String::from("Request time budget used"))));
+                }
+            };
+
+            match resp {
+                Ok((status, body)) => {
+                    if status.is_success() {
+                        attempts.push(Attempt::new(&key, &label, Some(status.as_u16()), "ok", started.elapsed()));
+                        self.key_manager.lock().await.mark_success(&key);
+                        return Ok(response_handler(serde_json::from_str::<R>(&body)?));
+                    }
+                    let upstream_error = serde_json::from_str::<ErrorResponse>(&body).ok();
+                    let reasons = upstream_error.as_ref().map(|error| error.get_reasons()).unwrap_or(vec![]);
+
+                    match KeyAction::from_response(status.as_u16(), &reasons) {
+                        KeyAction::Exhausted => {
+                            attempts.push(Attempt::new(&key, &label, Some(status.as_u16()), "key_exhausted", started.elapsed()));
+                            let disabled = self.key_manager.lock().await.set_key_as_expired(&key);
+                            if disabled {
+                                if let Err(error) = self.persist_keys().await {
+                                    logger::error(Some(ctx.get_request_id()), "Unable to save keys", &[("error", format!("{:?}", error))]);
+                                }
+                            }
+                        }
+                        KeyAction::RateLimited => {
+                            attempts.push(Attempt::new(&key, &label, Some(status.as_u16()), "rate_limited", started.elapsed()));
+                            last_error = ApiError::RateLimited(String::from("YouTube rate limit reached"), 1);
+                            if attempt + 1 < MAX_ATTEMPTS {
+                                wait_before_retry(ctx, attempt).await?;
+                            }
+                        }
+                        KeyAction::Disable => {
+                            attempts.push(Attempt::new(&key, &label, Some(status.as_u16()), "key_disabled", started.elapsed()));
+                            logger::warn(Some(ctx.get_request_id()), "Key disabled", &[
+                                ("resource", key_error_name.to_string()),
+                                ("key", mask_key(&key)),
+                                ("reasons", reasons.join(", "))]);
+                            self.key_manager.lock().await.disable_key(&key, &reasons.join(", "));
+                            if let Err(error) = self.persist_keys().await {
+                                logger::error(Some(ctx.get_request_id()), "Unable to save keys", &[("error", format!("{:?}", error))]);
+                            }
+                        }
+                        KeyAction::PassThrough => {
+                            logger::warn(Some(ctx.get_request_id()), "Upstream error", &[
+                                ("resource", key_error_name.to_string()),
+                                ("status", status.as_u16().to_string()),
+                                ("body", body.clone())]);
+                            let message = upstream_error.and_then(|error| error.get_message())
+                                .unwrap_or(format!("Error getting {}: {}", key_error_name, status.as_u16()));
+                            if status.is_client_error() {
+                                attempts.push(Attempt::new(&key, &label, Some(status.as_u16()), "client_error", started.elapsed()));
+                                return Err(Error::from(ApiError::UpstreamClient(message)));
+                            }
+                            attempts.push(Attempt::new(&key, &label, Some(status.as_u16()), "server_error", started.elapsed()));
+                            last_error = ApiError::UpstreamServer(message);
+                            if attempt + 1 < MAX_ATTEMPTS {
+                                wait_before_retry(ctx, attempt).await?;
+                            }
+                        }
+                    }
+                }
+                Err(err) => {
+                    if !(err.is_timeout() || err.is_connect()) {
+                        attempts.push(Attempt::new(&key, &label, None, "request_error", started.elapsed()));
+                        return Err(Error::from(err));
+                    }
+                    logger::warn(Some(ctx.get_request_id()), "Upstream request failed", &[
+                        ("resource", key_error_name.to_string()),
+                        ("error", err.to_string())]);
+                    if err.is_timeout() {
+                        attempts.push(Attempt::new(&key, &label, None, "timeout", started.elapsed()));
+                        last_error = ApiError::Timeout(String::from("YouTube did not respond in time"));
+                    } else {
+                        attempts.push(Attempt::new(&key, &label, None, "connection_error", started.elapsed()));
+                        last_error = ApiError::UpstreamServer(String::from("Unable to reach YouTube"));
+                    }
+                    if attempt + 1 < MAX_ATTEMPTS {
+                        wait_before_retry(ctx, attempt).await?;
+                    }
+                }
+            }
+        }
+
+        Err(Error::from(last_error))
+    }
+}
+
+/// None if the request's time budget runs out first, so a slow attempt is cut off rather than waited for
+async fn within_deadline<T, F: Future<Output = T>>(ctx: &RequestContext, future: F) -> Option<T> {
+    match ctx.remaining_time() {
+        Some(remaining) => tokio::time::timeout(remaining, future).await.ok(),
+        None => Some(future.await)
+    }
+}
+
+/// Sleeps before the next attempt, unless that would run past the request's deadline
+async fn wait_before_retry(ctx: &RequestContext, attempt: usize) -> Result<(), ApiError> {
+    let delay = backoff(attempt);
+    if ctx.remaining_time().is_some_and(|remaining| remaining <= delay) {
+        return Err(ApiError::BudgetExhausted(String::from("Request time budget used")));
     }
+    tokio::time::sleep(delay).await;
+    Ok(())
 }
\ No newline at end of file
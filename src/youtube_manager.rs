@@ -1,108 +1,360 @@
 use crate::key_manager::KeyManager;
 use std::time::Duration;
 use anyhow::Result;
-use reqwest::blocking::{Client};
 use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
 use crate::models::content_type::ContentType;
 use crate::models::channel::Channel;
 use crate::models::playlist::Playlist;
 use crate::models::video::Video;
+use crate::models::category::Category;
+use crate::models::region::Region;
+use crate::models::language::Language;
+use crate::models::activity::Activity;
+use crate::models::live_details::LiveDetails;
+use crate::models::page::Page;
 use crate::youtube_client::YoutubeClient;
+use crate::cache::Cache;
+use crate::retry::RequestDiagnostic;
+use crate::models::key_status::KeyStatus;
+use crate::key_store::KeyStore;
+use crate::models::reconciliation::Reconciliation;
+use crate::live_chat::{ChatRelay, ChatStream};
+use crate::request_context::RequestContext;
+use crate::clients::ClientRegistry;
+use crate::logger;
+use crate::models::health::HealthReport;
+use std::sync::Arc;
+use crate::audit::{AuditLog, Period};
+use crate::models::spend::SpendReport;
+use crate::error::ApiError;
+use chrono::{DateTime, Utc};
+use crate::usage::UsageLedger;
+use crate::quota;
+use crate::config::Config;
+use crate::http_client::HttpClient;
+use crate::models::key_test::KeyTest;
 
-const TIMEOUT: u64 = 120;
+pub const DEFAULT_REGION: &str = "US";
 
 pub struct YoutubeManager {
-    client: YoutubeClient
+    client: Arc<YoutubeClient>,
+    chat_relay: ChatRelay,
+    categories: Cache<String, Vec<Category>>,
+    regions: Cache<(), Vec<Region>>,
+    languages: Cache<(), Vec<Language>>,
+    trending: Cache<(String, Option<String>, Option<String>), Page<Video>>,
+    usage: Arc<UsageLedger>,
 }
 
 impl YoutubeManager {
-    pub fn new(key_manager: KeyManager, base_url: String, proxy: &Option<String>) -> YoutubeManager {
-        let mut builder = Client::builder()
-            .connect_timeout(Duration::from_secs(TIMEOUT))
-            .timeout(Duration::from_secs(TIMEOUT));
+    pub fn new(key_manager: KeyManager, base_url: String, config: &Config, key_store: Option<KeyStore>, audit_log: Option<AuditLog>, usage: UsageLedger) -> Result<YoutubeManager> {
+        let client = HttpClient::new(config)?;
 
-        if let Some(proxy) = proxy {
-            builder = builder.proxy(reqwest::Proxy::all(proxy).unwrap())
-        }
-
-        let client = builder.build().unwrap();
+        let usage = Arc::new(usage);
+        let youtube_client = YoutubeClient::new(key_manager, base_url, client, key_store, audit_log, usage.clone());
 
-        let youtube_client = YoutubeClient::new(key_manager, base_url, client);
+        let meta_ttl = Duration::from_secs(config.cache.meta_ttl);
 
-        return YoutubeManager {
-            client: youtube_client
-        };
+        Ok(YoutubeManager {
+            client: Arc::new(youtube_client),
+            chat_relay: ChatRelay::new(),
+            categories: Cache::new("categories", meta_ttl),
+            regions: Cache::new("regions", meta_ttl),
+            languages: Cache::new("languages", meta_ttl),
+            trending: Cache::new("trending", Duration::from_secs(config.cache.trending_ttl)),
+            usage,
+        })
     }
 }
 
 impl YoutubeManager {
-    pub fn get_key_status(&self) -> HashMap<usize, usize> {
-        self.client.get_key_status()
+    pub async fn get_key_status(&self) -> HashMap<usize, KeyStatus> {
+        self.client.get_key_status().await
+    }
+
+    pub async fn get_health(&self) -> HealthReport {
+        self.client.get_health().await
+    }
+
+    pub async fn get_quota_usage(&self) -> Vec<(String, usize, usize)> {
+        self.client.get_quota_usage().await
     }
 
-    pub fn reset_key_status(&self) {
-        self.client.reset_key_status();
+    pub async fn get_reconciliation(&self) -> Reconciliation {
+        self.client.get_reconciliation().await
     }
 
-    pub fn start_reset_timer(&self) {
-        self.client.start_timer();
+    pub async fn add_key(&self, key: String, label: String) -> Result<bool> {
+        self.client.update_keys(|key_manager| key_manager.add_key(key, label)).await
     }
 
-    pub fn single_video(&self, video_id: String) -> Result<Option<Video>> {
-        let result = self.client.single(ContentType::VIDEO, video_id)?;
-        let video = result.map(|item| item.into_video().unwrap());
+    pub async fn remove_key(&self, idx: usize) -> Result<bool> {
+        self.client.update_keys(|key_manager| key_manager.remove_key(idx)).await
+    }
+
+    pub async fn set_key_label(&self, idx: usize, label: String) -> Result<bool> {
+        self.client.update_keys(|key_manager| key_manager.set_label(idx, label)).await
+    }
+
+    pub async fn disable_key(&self, idx: usize) -> Result<bool> {
+        self.client.update_keys(|key_manager| key_manager.disable_key_at(idx, "Disabled by admin")).await
+    }
+
+    pub async fn enable_key(&self, idx: usize) -> Result<bool> {
+        self.client.update_keys(|key_manager| key_manager.enable_key(idx)).await
+    }
+
+    pub fn start_key_reload_listener(&self) -> Result<()> {
+        self.client.start_key_reload_listener()
+    }
+
+    pub async fn reset_key_status(&self) {
+        self.client.reset_key_status().await;
+    }
+
+    pub fn get_diagnostics(&self) -> Vec<RequestDiagnostic> {
+        self.client.get_diagnostics()
+    }
+
+    pub async fn test_keys(&self) -> Vec<KeyTest> {
+        self.client.test_keys().await
+    }
+
+    pub fn purge_caches(&self) {
+        self.categories.clear();
+        self.regions.clear();
+        self.languages.clear();
+        self.trending.clear();
+    }
+
+    pub async fn get_spend(&self, from: DateTime<Utc>, to: DateTime<Utc>, period: Period) -> Result<SpendReport, ApiError> {
+        self.client.get_spend(from, to, period).await
+    }
+
+    pub fn get_usage(&self) -> &UsageLedger {
+        &self.usage
+    }
+
+    pub async fn save_usage(&self) -> Result<()> {
+        UsageLedger::save_in_background(self.usage.clone()).await
+    }
+
+    pub fn start_usage_saving(&self) {
+        UsageLedger::start_saving(self.usage.clone());
+    }
+
+    /// [Cache::get_or_fetch] that also records the quota a hit saved, `resource` is what `fetch` calls
+    async fn cached<K: Eq + Hash + Clone, V: Clone, F: Future<Output = Result<V>>>(&self, cache: &Cache<K, V>, resource: &str, key: K, fetch: F) -> Result<V> {
+        let mut fetched = false;
+        let value = cache.get_or_fetch(key, || {
+            fetched = true;
+            fetch
+        }).await?;
+        if !fetched {
+            self.usage.record_cache_hit(quota::cost(resource, "list"));
+        }
+        Ok(value)
+    }
+
+    pub fn start_reset_timer(&self, clients: Arc<ClientRegistry>) {
+        self.client.start_timer(move || clients.reset());
+    }
+
+    /// The categories are fetched alongside the video, they're almost always cached
+    pub async fn single_video(&self, ctx: &RequestContext, video_id: String) -> Result<Option<Video>> {
+        let mut video = self.client.single(ctx, ContentType::VIDEO, video_id).await?.map(|item| item.into_video().unwrap());
+        // after the video so a missing one doesn't cost a categories call too
+        if let Some(video) = video.as_mut() {
+            if video.get_category_id().is_some() {
+                let categories = self.categories(ctx, DEFAULT_REGION.to_string()).await;
+                resolve_category(ctx, video, categories);
+            }
+        }
         Ok(video)
     }
 
-    pub fn single_channel(&self, channel_id: String) -> Result<Option<Channel>> {
-        let result = self.client.single(ContentType::CHANNEL, channel_id)?;
+    pub async fn categories(&self, ctx: &RequestContext, region: String) -> Result<Vec<Category>> {
+        let region = region.to_uppercase();
+        self.cached(&self.categories, "videoCategories", region.clone(), async {
+            let categories = self.client.video_categories(ctx, region).await?
+                .into_iter()
+                .map(|item| item.into_category())
+                .collect();
+            Ok(categories)
+        }).await
+    }
+
+    pub async fn regions(&self, ctx: &RequestContext) -> Result<Vec<Region>> {
+        self.cached(&self.regions, "i18nRegions", (), async {
+            let regions = self.client.i18n_regions(ctx).await?
+                .into_iter()
+                .map(|item| item.into_region())
+                .collect();
+            Ok(regions)
+        }).await
+    }
+
+    pub async fn languages(&self, ctx: &RequestContext) -> Result<Vec<Language>> {
+        self.cached(&self.languages, "i18nLanguages", (), async {
+            let languages = self.client.i18n_languages(ctx).await?
+                .into_iter()
+                .map(|item| item.into_language())
+                .collect();
+            Ok(languages)
+        }).await
+    }
+
+    pub async fn single_channel(&self, ctx: &RequestContext, channel_id: String) -> Result<Option<Channel>> {
+        self.usage.record_channel(&channel_id);
+        let result = self.client.single(ctx, ContentType::CHANNEL, channel_id).await?;
         let channel = result.map(|item| item.into_channel().unwrap());
         Ok(channel)
     }
 
-    pub fn single_playlist(&self, playlist_id: String) -> Result<Option<Playlist>> {
-        let result = self.client.single(ContentType::PLAYLIST, playlist_id)?;
+    pub async fn single_playlist(&self, ctx: &RequestContext, playlist_id: String) -> Result<Option<Playlist>> {
+        let result = self.client.single(ctx, ContentType::PLAYLIST, playlist_id).await?;
         let playlist = result.map(|item| item.into_playlist().unwrap());
         Ok(playlist)
     }
 
-    pub fn search_channel(&self, search_query: String) -> Result<Vec<Channel>> {
+    pub async fn search_channel(&self, ctx: &RequestContext, search_query: String) -> Result<Vec<Channel>> {
+        self.usage.record_query(&search_query);
         let search_params = vec![("q", search_query)];
-        let channels = self.client.search(ContentType::CHANNEL, search_params)?
+        let channels = self.client.search(ctx, ContentType::CHANNEL, search_params).await?
             .into_iter()
             .map(|item| item.into_channel().unwrap())
             .collect();
         Ok(channels)
     }
 
-    pub fn search_video(&self, search_query: String) -> Result<Vec<Video>> {
+    pub async fn search_video(&self, ctx: &RequestContext, search_query: String) -> Result<Vec<Video>> {
+        self.usage.record_query(&search_query);
         let search_params = vec![("q", search_query)];
-        let channels = self.client.search(ContentType::VIDEO, search_params)?
+        let channels = self.client.search(ctx, ContentType::VIDEO, search_params).await?
             .into_iter()
             .map(|item| item.into_video().unwrap())
             .collect();
         Ok(channels)
     }
 
-    pub fn search_playlist(&self, search_query: String) -> Result<Vec<Playlist>> {
+    pub async fn search_playlist(&self, ctx: &RequestContext, search_query: String) -> Result<Vec<Playlist>> {
+        self.usage.record_query(&search_query);
         let search_params = vec![("q", search_query)];
-        let channels = self.client.search(ContentType::PLAYLIST, search_params)?
+        let channels = self.client.search(ctx, ContentType::PLAYLIST, search_params).await?
             .into_iter()
             .map(|item| item.into_playlist().unwrap())
             .collect();
         Ok(channels)
     }
 
-    pub fn list_latest_videos_for_channel(&self, id: String) -> Result<Vec<Video>> {
+    pub async fn list_latest_videos_for_channel(&self, ctx: &RequestContext, id: String) -> Result<Vec<Video>> {
+        self.usage.record_channel(&id);
         let search_params = vec![("channelId", id)];
-        let videos = self.client.search(ContentType::VIDEO, search_params)?
+        let videos = self.client.search(ctx, ContentType::VIDEO, search_params).await?
+            .into_iter()
+            .map(|item| item.into_video().unwrap())
+            .collect();
+        Ok(videos)
+    }
+
+    pub async fn list_live_videos_for_channel(&self, ctx: &RequestContext, id: String) -> Result<Vec<Video>> {
+        self.list_event_videos_for_channel(ctx, id, "live").await
+    }
+
+    pub async fn list_upcoming_videos_for_channel(&self, ctx: &RequestContext, id: String) -> Result<Vec<Video>> {
+        self.list_event_videos_for_channel(ctx, id, "upcoming").await
+    }
+
+    async fn list_event_videos_for_channel(&self, ctx: &RequestContext, id: String, event_type: &'static str) -> Result<Vec<Video>> {
+        self.usage.record_channel(&id);
+        let search_params = vec![
+            ("channelId", id),
+            ("eventType", String::from(event_type))];
+        let mut videos: Vec<Video> = self.client.search(ctx, ContentType::VIDEO, search_params).await?
             .into_iter()
             .map(|item| item.into_video().unwrap())
             .collect();
+
+        if videos.is_empty() {
+            return Ok(videos);
+        }
+
+        let ids = videos.iter()
+            .map(|video| video.get_id().to_string())
+            .collect();
+        let mut details: HashMap<String, LiveDetails> = self.client.live_details(ctx, ids).await?
+            .into_iter()
+            .filter_map(|item| item.into_live_details())
+            .collect();
+
+        for video in videos.iter_mut() {
+            if let Some(live) = details.remove(video.get_id()) {
+                video.set_live_details(live);
+            }
+        }
         Ok(videos)
     }
 
-    pub fn list_videos_for_playlist(&self, id: String, page_token: Option<String>) -> Result<(Vec<Video>, Option<String>)> {
+    pub async fn trending(&self, ctx: &RequestContext, region: String, category: Option<String>, page_token: Option<String>) -> Result<Page<Video>> {
+        let region = region.to_uppercase();
+        let cache_key = (region.clone(), category.clone(), page_token.clone());
+        self.cached(&self.trending, "videos", cache_key, async {
+            let mut search_params = vec![("regionCode", region)];
+            if let Some(category) = category {
+                search_params.push(("videoCategoryId", category));
+            }
+            if let Some(token) = page_token {
+                search_params.push(("pageToken", token));
+            }
+
+            let (items, next_page_token) = self.client.most_popular(ctx, search_params).await?;
+            let videos = items.into_iter()
+                .map(|item| item.into_video().unwrap())
+                .collect();
+            Ok(Page::new(videos, next_page_token))
+        }).await
+    }
+
+    pub async fn live_chat_id(&self, ctx: &RequestContext, video_id: String) -> Result<Option<String>> {
+        let chat_id = self.client.live_details(ctx, vec![video_id]).await?
+            .into_iter()
+            .filter_map(|item| item.into_live_details())
+            .next()
+            .and_then(|(_, live)| live.get_active_chat_id());
+        Ok(chat_id)
+    }
+
+    /// Returns None if the video isn't currently live
+    /// Polls are billed to the longest listening client, so one that's used its budget is refused here
+    pub async fn live_chat_stream(&self, ctx: &RequestContext, video_id: String) -> Result<Option<ChatStream>> {
+        let poll_cost = quota::cost("liveChat/messages", "list");
+        ctx.reserve(poll_cost)?;
+        ctx.refund(poll_cost);
+        let stream = self.live_chat_id(ctx, video_id).await?
+            .map(|chat_id| self.chat_relay.subscribe(self.client.clone(), ctx, chat_id));
+        Ok(stream)
+    }
+
+    pub async fn list_activities_for_channel(&self, ctx: &RequestContext, id: String, published_after: Option<String>, published_before: Option<String>) -> Result<Vec<Activity>> {
+        self.usage.record_channel(&id);
+        let mut search_params = vec![("channelId", id)];
+        if let Some(after) = published_after {
+            search_params.push(("publishedAfter", after));
+        }
+        if let Some(before) = published_before {
+            search_params.push(("publishedBefore", before));
+        }
+
+        let activities = self.client.activities(ctx, search_params).await?
+            .into_iter()
+            .map(|item| item.into_activity())
+            .collect();
+        Ok(activities)
+    }
+
+    pub async fn list_videos_for_playlist(&self, ctx: &RequestContext, id: String, page_token: Option<String>) -> Result<(Vec<Video>, Option<String>)> {
         let mut search_params = vec![
             ("playlistId", id),
         ];
@@ -110,7 +362,7 @@ impl YoutubeManager {
             search_params.push(("pageToken", token));
         }
 
-        let (videos, page_token) = self.client.playlist_page(search_params)?;
+        let (videos, page_token) = self.client.playlist_page(ctx, search_params).await?;
 
         let videos = videos.into_iter()
             .map(|item| item.into_video().unwrap())
@@ -118,3 +370,18 @@ impl YoutubeManager {
         Ok((videos, page_token))
     }
 }
+
+fn resolve_category(ctx: &RequestContext, video: &mut Video, categories: Result<Vec<Category>>) {
+    if let Some(category_id) = video.get_category_id() {
+        match categories {
+            Ok(categories) => {
+                if let Some(category) = categories.iter().find(|category| category.get_id() == category_id) {
+                    video.set_category_title(category.get_title());
+                }
+            }
+            Err(error) => logger::warn(Some(ctx.get_request_id()), "Unable to resolve category", &[
+                ("categoryId", category_id.to_string()),
+                ("error", format!("{:?}", error))])
+        }
+    }
+}